@@ -1,7 +1,11 @@
-use std::borrow::Cow;
+use std::{borrow::Cow, collections::HashSet, fmt::Debug, hash::Hash};
 
-use crate::result::Result;
+use crate::{image::Image, result::Result};
 use async_trait::async_trait;
+use tracing::warn;
+
+/// How many requests in a row may bring nothing new before [Agent::get_batch] gives up.
+const MAX_STALLED_ROUNDS: usize = 3;
 #[async_trait]
 /// A trait for image retrieval agents.
 ///
@@ -10,45 +14,197 @@ use async_trait::async_trait;
 /// non-blocking operations when retrieving images from a source.
 ///
 /// # Methods
-/// - `get`: Asynchronously retrieves a single image and returns its URL as a `Cow<'_, str>`.
-/// - `get_many`: Asynchronously retrieves multiple images and returns their URLs as a `Vec<Cow<'_, str>>`.
-/// - `get_random`: Asynchronously retrieves a random image and returns its URL as a `Cow<'_, str>`.
+/// - `get`: Asynchronously retrieves a single [Image].
+/// - `get_many`: Asynchronously retrieves multiple [Image]s.
+/// - `get_random`: Asynchronously retrieves a random [Image].
+/// - `get_batch`: Asynchronously retrieves exactly `count` unique [Image]s
+///   (or fewer if the source runs dry).
+/// - `max_batch`: How many images a single `get_many` call returns at most.
+///
+/// An [Image] converts into [ImageUrl] when only the URL is needed.
 ///
 pub trait Agent {
     /// Retrieves a single image.
     ///
-    /// Returns image URL as a string reference.
+    /// Returns the image with its URL and metadata.
     ///
     /// # Errors
     /// Returns error if image cannot be retrieved.
     ///
     /// # Returns
-    /// - `Ok(Cow<'_, str>)` - URL of retrieved image
+    /// - `Ok(Image)` - retrieved image
     /// - `Err(Error)` - If retrieval fails
-    async fn get(&self) -> Result<ImageUrl<'_>>;
+    async fn get(&self) -> Result<Image>;
     /// Retrieves multiple images.
     ///
-    /// Returns vector of image URLs.
+    /// Returns a batch of images.
     ///
     /// # Errors
     /// Returns error if images cannot be retrieved.
     ///
     /// # Returns
-    /// - `Ok(Vec<Cow<'_, str>>)` - URLs of retrieved images
+    /// - `Ok(Images)` - retrieved images
     /// - `Err(Error)` - If retrieval fails
-    async fn get_many(&self) -> Result<ImageUrls<'_>>;
+    async fn get_many(&self) -> Result<Images>;
     /// Retrieves a random image.
     ///
-    /// Returns URL of random image.
+    /// Returns a random image.
     ///
     /// # Errors
     /// Returns error if image cannot be retrieved.
     ///
     /// # Returns
-    /// - `Ok(Cow<'_, str>)` - URL of random image
+    /// - `Ok(Image)` - random image
     /// - `Err(Error)` - If retrieval fails
-    async fn get_random(&self) -> Result<ImageUrl<'_>>;
+    async fn get_random(&self) -> Result<Image>;
+    /// Retrieves `count` unique images.
+    ///
+    /// Calls `get_many` as many times as needed, drops duplicates and trims
+    /// the result to `count`. Stops early when several calls in a row bring
+    /// no new images, so the result may be shorter than requested.
+    ///
+    /// # Errors
+    /// Returns error if any of the underlying requests fails.
+    ///
+    /// # Returns
+    /// - `Ok(Images)` - at most `count` unique images
+    /// - `Err(Error)` - If retrieval fails
+    async fn get_batch(&self, count: usize) -> Result<Images> {
+        let mut batch = Batch::new(count);
+        while !batch.is_done() {
+            batch.extend(self.get_many().await?);
+        }
+        Ok(batch.finish())
+    }
+    /// Maximum number of images returned by a single `get_many` call.
+    ///
+    /// Wrappers like [Prefetched](crate::layers::prefetch::Prefetched) size
+    /// their buffers from it, so report what the source really returns.
+    fn max_batch(&self) -> usize;
+}
+
+#[async_trait]
+/// An [Agent] whose images are grouped into categories.
+///
+/// Lets generic code list the categories of an agent and fetch from any of
+/// them without mutating the agent, so one instance can serve several
+/// categories at once.
+///
+/// # Example
+/// ```rust
+/// use anime_grubber::agent::CategorizedAgent;
+///
+/// async fn first_of_each<A: CategorizedAgent + Sync>(agent: &A) -> anime_grubber::Result<()> {
+///     for category in A::categories() {
+///         let image = agent.get_in(category).await?;
+///         println!("{category:?}: {image}");
+///     }
+///     Ok(())
+/// }
+/// ```
+pub trait CategorizedAgent: Agent {
+    /// Category type of the agent.
+    type Category: Copy + Eq + Hash + Debug + Send + Sync + 'static;
+
+    /// Lists every category of the agent.
+    fn categories() -> Vec<Self::Category>
+    where
+        Self: Sized;
+    /// Category used by [Agent::get] and [Agent::get_many].
+    fn category(&self) -> Self::Category;
+    /// Changes the category used by [Agent::get] and [Agent::get_many].
+    fn set_category(&mut self, category: Self::Category);
+    /// Retrieves a single image of `category`.
+    ///
+    /// # Errors
+    /// Returns error if image cannot be retrieved.
+    async fn get_in(&self, category: Self::Category) -> Result<Image>;
+    /// Retrieves multiple images of `category`.
+    ///
+    /// # Errors
+    /// Returns error if images cannot be retrieved.
+    async fn get_many_in(&self, category: Self::Category) -> Result<Images>;
+}
+
+#[async_trait]
+impl<A> Agent for Box<A>
+where
+    A: Agent + Send + Sync + ?Sized,
+{
+    async fn get(&self) -> Result<Image> {
+        (**self).get().await
+    }
+    async fn get_many(&self) -> Result<Images> {
+        (**self).get_many().await
+    }
+    async fn get_random(&self) -> Result<Image> {
+        (**self).get_random().await
+    }
+    async fn get_batch(&self, count: usize) -> Result<Images> {
+        (**self).get_batch(count).await
+    }
+    fn max_batch(&self) -> usize {
+        (**self).max_batch()
+    }
 }
 
 pub type ImageUrl<'a> = Cow<'a, str>;
 pub type ImageUrls<'a> = Box<[Cow<'a, str>]>;
+pub type Images = Box<[Image]>;
+
+/// Accumulates unique images for [Agent::get_batch].
+pub(crate) struct Batch {
+    count: usize,
+    images: Vec<Image>,
+    seen: HashSet<String>,
+    stalled: usize,
+}
+
+impl Batch {
+    pub(crate) fn new(count: usize) -> Self {
+        Self {
+            count,
+            images: Vec::with_capacity(count),
+            seen: HashSet::with_capacity(count),
+            stalled: 0,
+        }
+    }
+
+    /// Whether enough images were collected or the source stopped giving new ones.
+    pub(crate) fn is_done(&self) -> bool {
+        self.images.len() >= self.count || self.stalled >= MAX_STALLED_ROUNDS
+    }
+
+    /// URLs collected so far.
+    pub(crate) fn urls(&self) -> impl Iterator<Item = &str> {
+        self.images.iter().map(|image| image.url.as_str())
+    }
+
+    pub(crate) fn extend(&mut self, images: Images) {
+        let before = self.images.len();
+        for image in images.into_vec() {
+            if self.images.len() >= self.count {
+                break;
+            }
+            if self.seen.insert(image.url.clone()) {
+                self.images.push(image);
+            }
+        }
+        if self.images.len() == before {
+            self.stalled += 1;
+        } else {
+            self.stalled = 0;
+        }
+    }
+
+    pub(crate) fn finish(self) -> Images {
+        if self.images.len() < self.count {
+            warn!(
+                "Collected {} of {} requested images",
+                self.images.len(),
+                self.count
+            );
+        }
+        self.images.into_boxed_slice()
+    }
+}
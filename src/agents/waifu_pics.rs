@@ -1,65 +1,66 @@
-use std::borrow::Cow;
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 
-use crate::agent::{ImageUrl, ImageUrls};
+use crate::agent::{Batch, Images};
+use crate::context::{
+    AgentContext, DEFAULT_POOL_IDLE_TIMEOUT, DEFAULT_POOL_MAX_IDLE, DEFAULT_TIMEOUT,
+};
+use crate::download::Downloader;
 use crate::error::Error;
+use crate::image::Image;
+use crate::middleware::Middleware;
+use crate::proxy::ProxyConfig;
+use crate::registry::{AgentFactory, BoxedAgent};
 use crate::result::Result;
-use crate::{agent::Agent, gen_enum, url};
+use crate::status::error_for_status;
+use crate::transport::{Request, Transport};
+use crate::{
+    agent::{Agent, CategorizedAgent},
+    gen_enum, url,
+};
 use async_trait::async_trait;
 use miniserde::{json, Deserialize, Serialize};
-use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE};
-use reqwest::StatusCode;
-use tracing::{debug, info, instrument, trace};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE};
+use tracing::{debug, info, instrument};
 
+/// Name of the agent in [Image::agent] and in the [AgentRegistry](crate::registry::AgentRegistry).
+pub const AGENT_NAME: &str = "waifu_pics";
 const SOLO_URL: &str = "https://api.waifu.pics";
-const MANY_URL: &str = "https://api.waifu.pics/many";
-const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
-const DEFAULT_POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
-const DEFAULT_POOL_MAX_IDLE: usize = 32;
+/// Amount of files returned by `/many`.
+pub const MAX_BATCH: usize = 30;
 #[derive(Debug, Clone)]
 /// An image-fetching agent from the [Waifu.pics API](https://waifu.pics/docs).
 ///
 /// This crate provides methods to retrieve images based on specified categories.
 /// It supports fetching single images, multiple images and random images.
-/// The images are returned as `Cow<str>` containing URLs.
+/// The images are returned as [Image] and can be turned into `Cow<str>` URLs.
 ///
 /// # Examples
 /// ```rust
-/// use anime_grubber::agent::Agent;
+/// use anime_grubber::agent::{Agent, ImageUrl};
 /// use anime_grubber::agents::waifu_pics::{Waifu, Categories, SFW};
-/// use std::borrow::Cow;
+/// use anime_grubber::image::Image;
 /// async fn example() {
 ///     let mut waifu = Waifu::new(Categories::SFW(SFW::Dance));
 ///
 ///     // Get single image URL
-///     let image_url: Cow<str> = waifu.get().await.unwrap();
+///     let image_url: ImageUrl = waifu.get().await.unwrap().into();
 ///
-///     // Get multiple image URLs
-///     let many_urls: Box<[Cow<str>]> = waifu.get_many().await.unwrap();
+///     // Get multiple images
+///     let many: Box<[Image]> = waifu.get_many().await.unwrap();
 ///
-///     // Get random image URL
-///     let random_url: Cow<str> = waifu.get_random().await.unwrap();
+///     // Get random image
+///     let random: Image = waifu.get_random().await.unwrap();
 /// }
 /// ```
 pub struct Waifu {
     pub categorie: Categories,
-    client: reqwest::Client,
+    random_pool: Option<Box<[Categories]>>,
+    base_url: String,
+    context: AgentContext,
 }
 impl Default for Waifu {
     fn default() -> Self {
-        let client = reqwest::Client::builder()
-            .timeout(DEFAULT_TIMEOUT)
-            .pool_idle_timeout(DEFAULT_POOL_IDLE_TIMEOUT)
-            .pool_max_idle_per_host(DEFAULT_POOL_MAX_IDLE)
-            .default_headers({
-                let mut headers = HeaderMap::new();
-                headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
-                headers
-            })
-            .build()
-            .expect("Failed to create HTTP client");
-        let categorie = Categories::default();
-        Self { categorie, client }
+        Self::new(Categories::default())
     }
 }
 impl PartialEq for Waifu {
@@ -78,6 +79,9 @@ impl Waifu {
     /// # Returns
     /// Returns a new `Waifu` instance.
     ///
+    /// # Panics
+    /// Panics if the HTTP client cannot be created, see [Waifu::try_new].
+    ///
     /// # Example
     /// ```rust
     /// use anime_grubber::agents::waifu_pics::{Waifu, Categories, SFW};
@@ -86,19 +90,99 @@ impl Waifu {
     /// ```
     #[instrument(skip(categorie))]
     pub fn new(categorie: Categories) -> Self {
-        let client = reqwest::Client::builder()
-            .timeout(DEFAULT_TIMEOUT)
-            .pool_idle_timeout(DEFAULT_POOL_IDLE_TIMEOUT)
-            .pool_max_idle_per_host(DEFAULT_POOL_MAX_IDLE)
-            .default_headers({
-                let mut headers = HeaderMap::new();
-                headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
-                headers
-            })
+        Self::try_new(categorie).expect("Failed to create HTTP client")
+    }
+
+    /// Creates a new instance of `Waifu` with the specified category.
+    ///
+    /// The agent uses the crate-level [AgentContext::shared] connection pool.
+    ///
+    /// # Errors
+    /// Returns [Error::Client] if the HTTP client cannot be created.
+    ///
+    /// # Example
+    /// ```rust
+    /// use anime_grubber::agents::waifu_pics::{Waifu, Categories, SFW};
+    ///
+    /// let waifu = Waifu::try_new(Categories::SFW(SFW::Dance)).expect("no TLS backend");
+    /// ```
+    #[instrument(skip(categorie))]
+    pub fn try_new(categorie: Categories) -> Result<Self> {
+        Self::builder()
+            .categorie(categorie)
+            .context(&AgentContext::shared()?)
             .build()
-            .expect("Failed to create HTTP client");
+    }
+
+    /// Creates a copy of the agent for another category.
+    ///
+    /// The copy shares the transport and all settings with the original.
+    ///
+    /// # Example
+    /// ```rust
+    /// use anime_grubber::agents::waifu_pics::{Waifu, Categories, SFW};
+    ///
+    /// let hug = Waifu::new(Categories::SFW(SFW::Hug));
+    /// let pat = hug.for_categorie(Categories::SFW(SFW::Pat));
+    /// assert_eq!(pat.categorie, Categories::SFW(SFW::Pat));
+    /// ```
+    pub fn for_categorie(&self, categorie: Categories) -> Self {
+        Self {
+            categorie,
+            ..self.clone()
+        }
+    }
+
+    /// Returns the context holding the transport of this agent.
+    pub fn context(&self) -> &AgentContext {
+        &self.context
+    }
+
+    /// Creates a [Downloader] fetching image files through the transport of this agent.
+    pub fn downloader(&self) -> Downloader {
+        Downloader::new(self.context.clone())
+    }
+
+    /// Creates a [WaifuBuilder] to configure the agent and its HTTP client.
+    ///
+    /// # Example
+    /// ```rust
+    /// use std::time::Duration;
+    /// use anime_grubber::agents::waifu_pics::{Waifu, Categories, SFW};
+    ///
+    /// let waifu = Waifu::builder()
+    ///     .categorie(Categories::SFW(SFW::Hug))
+    ///     .base_url("https://waifu.example.com")
+    ///     .timeout(Duration::from_secs(5))
+    ///     .user_agent("my-bot/1.0")
+    ///     .build()
+    ///     .expect("Failed to create HTTP client");
+    /// assert_eq!(waifu.base_url(), "https://waifu.example.com");
+    /// ```
+    pub fn builder() -> WaifuBuilder {
+        WaifuBuilder::default()
+    }
+
+    /// Points the agent to another instance of the API.
+    ///
+    /// # Parameters
+    /// - `base_url`: URL of the API, `https://api.waifu.pics` by default.
+    ///
+    /// # Example
+    /// ```rust
+    /// use anime_grubber::agents::waifu_pics::{Waifu, Categories};
+    ///
+    /// let waifu = Waifu::new(Categories::default()).with_base_url("http://127.0.0.1:8080/");
+    /// assert_eq!(waifu.base_url(), "http://127.0.0.1:8080");
+    /// ```
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into().trim_end_matches('/').to_owned();
+        self
+    }
 
-        Self { categorie, client }
+    /// Returns the URL of the API this agent talks to.
+    pub fn base_url(&self) -> &str {
+        &self.base_url
     }
 
     /// Updates the category of the `Waifu` instance.
@@ -117,62 +201,426 @@ impl Waifu {
     pub fn set_categorie(&mut self, categorie: Categories) {
         self.categorie = categorie;
     }
+
+    /// Restricts the categories `get_random` picks from.
+    ///
+    /// By default `get_random` picks any category of the same kind (SFW or NSFW)
+    /// as the current one. An empty pool restores the default.
+    ///
+    /// # Parameters
+    /// - `pool`: Categories to choose from.
+    ///
+    /// # Example
+    /// ```rust
+    /// use anime_grubber::agents::waifu_pics::{Waifu, Categories, SFW};
+    ///
+    /// let waifu = Waifu::new(Categories::SFW(SFW::Hug))
+    ///     .with_random_pool([SFW::Hug, SFW::Pat, SFW::Cuddle].map(Categories::SFW));
+    /// ```
+    pub fn with_random_pool(mut self, pool: impl IntoIterator<Item = Categories>) -> Self {
+        let pool: Box<[Categories]> = pool.into_iter().collect();
+        self.random_pool = (!pool.is_empty()).then_some(pool);
+        self
+    }
+
+    /// Picks a category for `get_random`.
+    fn random_categorie(&self) -> Categories {
+        let pool = match (&self.random_pool, self.categorie) {
+            (Some(pool), _) => pool.to_vec(),
+            (None, Categories::SFW(_)) => {
+                SFW::variants().into_iter().map(Categories::SFW).collect()
+            }
+            (None, Categories::NSFW(_)) => {
+                NSFW::variants().into_iter().map(Categories::NSFW).collect()
+            }
+        };
+        pool[fastrand::usize(..pool.len())]
+    }
+
+    /// Retrieves a single image of the given category.
+    async fn fetch(&self, categorie: Categories) -> Result<Image> {
+        let category: &str = (&categorie).into();
+        let aspect = categorie.nested_str();
+        let url = url!(self.base_url, category, aspect);
+
+        let res = self.context.transport().send(Request::get(url)).await?;
+        let res_text = error_for_status(res)?.text();
+
+        let conveted = json::from_str::<SoloImage>(&res_text)?;
+
+        Ok(image(conveted.url, categorie))
+    }
+
+    /// Retrieves multiple images, skipping the ones that were already seen.
+    ///
+    /// # Parameters
+    /// - `exclude`: URLs or file names the API must not return again.
+    ///
+    /// # Errors
+    /// Returns error if images cannot be retrieved.
+    ///
+    /// # Example
+    /// ```rust
+    /// use anime_grubber::agent::Agent;
+    /// use anime_grubber::agents::waifu_pics::{Waifu, Categories, SFW};
+    ///
+    /// async fn example() -> anime_grubber::Result<()> {
+    ///     let waifu = Waifu::new(Categories::SFW(SFW::Hug));
+    ///     let shown = waifu.get_many().await?;
+    ///     let fresh = waifu.get_many_excluding(shown.iter()).await?;
+    ///     Ok(())
+    /// }
+    /// ```
+    #[instrument(skip(self, exclude))]
+    pub async fn get_many_excluding<I, S>(&self, exclude: I) -> Result<Images>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        info!("Fetch many data");
+        let exclude: Vec<S> = exclude.into_iter().collect();
+        self.fetch_many(self.categorie, &exclude).await
+    }
+
+    /// Retrieves multiple images of the given category.
+    async fn fetch_many<S: AsRef<str>>(
+        &self,
+        categorie: Categories,
+        exclude: &[S],
+    ) -> Result<Images> {
+        let category: &str = (&categorie).into();
+        let aspect = categorie.nested_str();
+        let url = url!(format!("{}/many", self.base_url), category, aspect);
+
+        let body = Body {
+            exclude: exclude.iter().map(AsRef::as_ref).collect(),
+        };
+        debug!("Exclude {} files", body.exclude.len());
+
+        let request = Request::post(url)
+            .header(CONTENT_TYPE, HeaderValue::from_static("application/json"))
+            .body(json::to_string(&body));
+        let res = self.context.transport().send(request).await?;
+        let res_text = error_for_status(res)?.text();
+        let conveted = json::from_str::<ManyImages>(&res_text)?;
+
+        Ok(conveted
+            .files
+            .into_iter()
+            .map(|url| image(url, categorie))
+            .collect())
+    }
+}
+
+/// Image at `url` returned for `categorie`.
+fn image(url: String, categorie: Categories) -> Image {
+    let category: &str = (&categorie).into();
+    Image::new(url, AGENT_NAME, category.to_lowercase())
+        .with_aspect(categorie.nested_str().to_lowercase())
 }
 
 #[async_trait]
 impl Agent for Waifu {
     #[instrument(skip(self))]
-    async fn get(&self) -> Result<ImageUrl<'_>> {
+    async fn get(&self) -> Result<Image> {
         info!("Fetch data");
-        let category: &str = (&self.categorie).into();
-        let aspect = self.categorie.nested_str();
-        let url = url!(SOLO_URL, category, aspect);
+        self.fetch(self.categorie).await
+    }
 
-        let res = self.client.get(url).send().await?;
-        if res.status() == reqwest::StatusCode::NOT_FOUND {
-            return Err(Error::NotFound);
+    #[instrument(skip(self))]
+    async fn get_many(&self) -> Result<Images> {
+        self.get_many_excluding(std::iter::empty::<&str>()).await
+    }
+
+    /// Fetches an image from a random category, see [Waifu::with_random_pool].
+    ///
+    /// The chosen category is stored in [Image::category] and [Image::aspect].
+    #[instrument(skip(self))]
+    async fn get_random(&self) -> Result<Image> {
+        let categorie = self.random_categorie();
+        info!("Fetch random data from {categorie:?}");
+        self.fetch(categorie).await
+    }
+
+    /// Collects `count` unique images, asking the API to exclude
+    /// everything collected so far on each subsequent request.
+    #[instrument(skip(self))]
+    async fn get_batch(&self, count: usize) -> Result<Images> {
+        info!("Fetch batch of {count}");
+        let mut batch = Batch::new(count);
+        while !batch.is_done() {
+            let images = self.get_many_excluding(batch.urls()).await?;
+            batch.extend(images);
         }
-        debug!("Response received: status={}", res.status());
-        trace!("res -> {:#?}", res);
-        let res_text = res.text().await?;
+        Ok(batch.finish())
+    }
 
-        let conveted = json::from_str::<SoloImage>(&res_text)?;
+    fn max_batch(&self) -> usize {
+        MAX_BATCH
+    }
+}
+
+#[async_trait]
+impl CategorizedAgent for Waifu {
+    type Category = Categories;
 
-        Ok(conveted.into())
+    fn categories() -> Vec<Categories> {
+        Categories::variants()
+    }
+
+    fn category(&self) -> Categories {
+        self.categorie
+    }
+
+    fn set_category(&mut self, category: Categories) {
+        self.set_categorie(category);
+    }
+
+    #[instrument(skip(self))]
+    async fn get_in(&self, category: Categories) -> Result<Image> {
+        info!("Fetch data");
+        self.fetch(category).await
     }
 
     #[instrument(skip(self))]
-    async fn get_many(&self) -> Result<ImageUrls<'_>> {
+    async fn get_many_in(&self, category: Categories) -> Result<Images> {
         info!("Fetch many data");
-        let category: &str = (&self.categorie).into();
-        let aspect = self.categorie.nested_str();
-        let url = url!(MANY_URL, category, aspect);
-
-        let res = self
-            .client
-            .post(url)
-            .body(json::to_string(&Body::default()))
-            .send()
-            .await?;
-        if !res.status().is_success() {
-            return match res.status() {
-                StatusCode::NOT_FOUND => Err(Error::NotFound),
-                StatusCode::TOO_MANY_REQUESTS => Err(Error::RateLimit),
-                status => Err(Error::RequestFailed(status)),
-            };
+        self.fetch_many::<&str>(category, &[]).await
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Builder for [Waifu].
+///
+/// Unless a ready client is passed with [WaifuBuilder::client],
+/// [WaifuBuilder::context] or [WaifuBuilder::transport], the builder creates a new HTTP client with its
+/// own connection pool. Otherwise the HTTP settings of the builder
+/// (timeouts, pool, headers, user agent, proxy, certificates) are ignored.
+pub struct WaifuBuilder {
+    categorie: Categories,
+    random_pool: Option<Box<[Categories]>>,
+    base_url: String,
+    timeout: Duration,
+    pool_idle_timeout: Duration,
+    pool_max_idle_per_host: usize,
+    headers: HeaderMap,
+    user_agent: Option<String>,
+    proxy: Option<ProxyConfig>,
+    #[cfg(any(feature = "native-tls", feature = "rustls-tls"))]
+    root_certificates: Vec<reqwest::Certificate>,
+    context: Option<AgentContext>,
+    middleware: Vec<Arc<dyn Middleware>>,
+}
+
+impl Default for WaifuBuilder {
+    fn default() -> Self {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        Self {
+            categorie: Categories::default(),
+            random_pool: None,
+            base_url: SOLO_URL.to_owned(),
+            timeout: DEFAULT_TIMEOUT,
+            pool_idle_timeout: DEFAULT_POOL_IDLE_TIMEOUT,
+            pool_max_idle_per_host: DEFAULT_POOL_MAX_IDLE,
+            headers,
+            user_agent: None,
+            proxy: None,
+            #[cfg(any(feature = "native-tls", feature = "rustls-tls"))]
+            root_certificates: Vec::new(),
+            context: None,
+            middleware: Vec::new(),
         }
-        debug!("Response received: status={}", res.status());
-        trace!("res -> {:#?}", res);
-        let res_text = res.text().await?;
-        let conveted = json::from_str::<ManyImages>(&res_text)?;
+    }
+}
 
-        Ok(conveted.into())
+impl WaifuBuilder {
+    /// Sets the category of images to fetch.
+    pub fn categorie(mut self, categorie: Categories) -> Self {
+        self.categorie = categorie;
+        self
     }
 
-    #[instrument(skip(self))]
-    async fn get_random(&self) -> Result<ImageUrl<'_>> {
-        info!("Fetch \"random\" data");
-        self.get().await
+    /// Restricts the categories `get_random` picks from, see [Waifu::with_random_pool].
+    pub fn random_pool(mut self, pool: impl IntoIterator<Item = Categories>) -> Self {
+        let pool: Box<[Categories]> = pool.into_iter().collect();
+        self.random_pool = (!pool.is_empty()).then_some(pool);
+        self
+    }
+
+    /// Sets the URL of the API, `https://api.waifu.pics` by default.
+    pub fn base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into().trim_end_matches('/').to_owned();
+        self
+    }
+
+    /// Sets the timeout of a whole request, 30 seconds by default.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets how long idle connections are kept, 90 seconds by default.
+    pub fn pool_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.pool_idle_timeout = timeout;
+        self
+    }
+
+    /// Sets the maximum of idle connections per host, 32 by default.
+    pub fn pool_max_idle_per_host(mut self, max: usize) -> Self {
+        self.pool_max_idle_per_host = max;
+        self
+    }
+
+    /// Adds headers sent with every request, replacing ones with the same name.
+    pub fn default_headers(mut self, headers: HeaderMap) -> Self {
+        self.headers.extend(headers);
+        self
+    }
+
+    /// Adds a header sent with every request.
+    pub fn header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.insert(name, value);
+        self
+    }
+
+    /// Sets the `User-Agent` header.
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = Some(user_agent.into());
+        self
+    }
+
+    /// Routes every request through a proxy.
+    pub fn proxy(mut self, proxy: ProxyConfig) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Trusts an additional root certificate, e.g. of a corporate TLS proxy.
+    ///
+    /// Available with the `native-tls` or `rustls-tls` feature.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// use anime_grubber::agents::waifu_pics::Waifu;
+    ///
+    /// let pem = std::fs::read("corporate-ca.pem").expect("certificate");
+    /// let certificate = reqwest::Certificate::from_pem(&pem).expect("valid PEM");
+    /// let waifu = Waifu::builder()
+    ///     .add_root_certificate(certificate)
+    ///     .build()
+    ///     .expect("Failed to create HTTP client");
+    /// ```
+    #[cfg(any(feature = "native-tls", feature = "rustls-tls"))]
+    pub fn add_root_certificate(mut self, certificate: reqwest::Certificate) -> Self {
+        self.root_certificates.push(certificate);
+        self
+    }
+
+    /// Uses a pre-configured client instead of building a new one.
+    pub fn client(self, client: reqwest::Client) -> Self {
+        self.context(&AgentContext::new(client))
+    }
+
+    /// Uses the transport of a shared [AgentContext] instead of building a new client.
+    pub fn context(mut self, context: &AgentContext) -> Self {
+        self.context = Some(context.clone());
+        self
+    }
+
+    /// Runs every request through `middleware`, after the middleware added before.
+    ///
+    /// Unlike the HTTP settings, middleware is also added to a client, context
+    /// or transport passed to the builder, see [AgentContext::with_middleware].
+    pub fn middleware(mut self, middleware: impl Middleware + 'static) -> Self {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    /// Sends requests through `transport` instead of building a new client.
+    pub fn transport(self, transport: impl Transport + 'static) -> Self {
+        self.context(&AgentContext::with_transport(transport))
+    }
+
+    /// Builds the [Waifu].
+    ///
+    /// # Errors
+    /// Returns [Error::Client] if the HTTP client cannot be created,
+    /// e.g. when the user agent is not a valid header value or the proxy URL is invalid.
+    pub fn build(self) -> Result<Waifu> {
+        let context = match self.context {
+            Some(context) => context,
+            None => {
+                let mut builder = reqwest::Client::builder()
+                    .timeout(self.timeout)
+                    .pool_idle_timeout(self.pool_idle_timeout)
+                    .pool_max_idle_per_host(self.pool_max_idle_per_host)
+                    .default_headers(self.headers);
+                if let Some(user_agent) = self.user_agent {
+                    builder = builder.user_agent(user_agent);
+                }
+                if let Some(proxy) = self.proxy {
+                    builder = builder.proxy(proxy.build()?);
+                }
+                #[cfg(any(feature = "native-tls", feature = "rustls-tls"))]
+                for certificate in self.root_certificates {
+                    builder = builder.add_root_certificate(certificate);
+                }
+                AgentContext::new(builder.build().map_err(Error::Client)?)
+            }
+        };
+        let context = self
+            .middleware
+            .into_iter()
+            .fold(context, AgentContext::with_shared_middleware);
+        Ok(Waifu {
+            categorie: self.categorie,
+            random_pool: self.random_pool,
+            base_url: self.base_url,
+            context,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// Creates [Waifu] agents for the [AgentRegistry](crate::registry::AgentRegistry).
+///
+/// Categories are written as in [Categories::path], e.g. `sfw/hug`.
+pub struct WaifuFactory {
+    context: Option<AgentContext>,
+}
+
+impl WaifuFactory {
+    /// Makes every created agent use the given context instead of the shared one.
+    pub fn with_context(context: AgentContext) -> Self {
+        Self {
+            context: Some(context),
+        }
+    }
+}
+
+impl AgentFactory for WaifuFactory {
+    fn categories(&self) -> Vec<String> {
+        Categories::variants()
+            .iter()
+            .map(Categories::path)
+            .collect()
+    }
+
+    fn build(&self, category: Option<&str>) -> Result<BoxedAgent> {
+        let categorie = category
+            .map(str::parse::<Categories>)
+            .transpose()?
+            .unwrap_or_default();
+        let context = match &self.context {
+            Some(context) => context.clone(),
+            None => AgentContext::shared()?,
+        };
+        let waifu = Waifu::builder()
+            .categorie(categorie)
+            .context(&context)
+            .build()?;
+        Ok(Box::new(waifu))
     }
 }
 
@@ -180,12 +628,7 @@ impl Agent for Waifu {
 struct SoloImage {
     url: String,
 }
-impl<'a> From<SoloImage> for Cow<'a, str> {
-    fn from(value: SoloImage) -> Self {
-        Cow::Owned(value.url)
-    }
-}
-#[derive(Debug, Serialize, Default)]
+#[derive(Debug, Serialize)]
 struct Body<'a> {
     exclude: Box<[&'a str]>,
 }
@@ -194,11 +637,6 @@ struct Body<'a> {
 struct ManyImages {
     files: Vec<String>,
 }
-impl<'a> From<ManyImages> for Box<[Cow<'a, str>]> {
-    fn from(value: ManyImages) -> Self {
-        value.files.into_iter().map(Cow::Owned).collect()
-    }
-}
 
 gen_enum!(
     SFW,
@@ -0,0 +1,137 @@
+use std::{
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
+
+use crate::{
+    error::Error,
+    middleware::{Chain, Middleware},
+    proxy::ProxyConfig,
+    result::Result,
+    transport::{ReqwestTransport, Transport},
+};
+use tracing::{debug, instrument};
+
+pub(crate) const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+pub(crate) const DEFAULT_POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+pub(crate) const DEFAULT_POOL_MAX_IDLE: usize = 32;
+
+static SHARED: OnceLock<AgentContext> = OnceLock::new();
+
+#[derive(Debug, Clone)]
+/// HTTP resources which can be shared between agents.
+///
+/// Agents send their requests through the [Transport] of the context,
+/// a [ReqwestTransport] unless another one is given with [AgentContext::with_transport],
+/// after passing them through the [Middleware] of the context.
+/// Cloning is cheap: every clone uses the same transport and connection pool.
+/// [AgentContext::shared] returns the crate-level context which
+/// [Waifu::new](crate::agents::waifu_pics::Waifu::new) uses by default.
+///
+/// # Example
+/// ```rust
+/// use anime_grubber::context::AgentContext;
+/// use anime_grubber::agents::waifu_pics::{Waifu, Categories, SFW};
+///
+/// let context = AgentContext::shared().expect("Failed to create HTTP client");
+/// let hug = Waifu::builder()
+///     .categorie(Categories::SFW(SFW::Hug))
+///     .context(&context)
+///     .build()
+///     .expect("Failed to create HTTP client");
+/// let pat = hug.for_categorie(Categories::SFW(SFW::Pat));
+/// ```
+pub struct AgentContext {
+    chain: Chain,
+}
+
+impl AgentContext {
+    /// Wraps a pre-configured client.
+    pub fn new(client: reqwest::Client) -> Self {
+        Self::with_transport(ReqwestTransport::new(client))
+    }
+
+    /// Sends every request through `transport`.
+    pub fn with_transport(transport: impl Transport + 'static) -> Self {
+        Self {
+            chain: Chain::new(Arc::new(transport)),
+        }
+    }
+
+    /// Runs every request through `middleware`, after the middleware added before.
+    ///
+    /// Other clones of the context are not affected.
+    ///
+    /// # Example
+    /// ```rust
+    /// use anime_grubber::context::AgentContext;
+    /// use anime_grubber::middleware::before_fn;
+    /// use reqwest::header::HeaderValue;
+    ///
+    /// let context = AgentContext::shared()
+    ///     .expect("Failed to create HTTP client")
+    ///     .with_middleware(before_fn(|request| {
+    ///         request
+    ///             .headers
+    ///             .insert("x-correlation-id", HeaderValue::from_static("42"));
+    ///     }));
+    /// ```
+    pub fn with_middleware(self, middleware: impl Middleware + 'static) -> Self {
+        self.with_shared_middleware(Arc::new(middleware))
+    }
+
+    pub(crate) fn with_shared_middleware(mut self, middleware: Arc<dyn Middleware>) -> Self {
+        self.chain = self.chain.with(middleware);
+        self
+    }
+
+    /// Creates a context with its own connection pool and default settings.
+    ///
+    /// # Errors
+    /// Returns [Error::Client] if the HTTP client cannot be created.
+    #[instrument]
+    pub fn try_default() -> Result<Self> {
+        let client = default_client().build().map_err(Error::Client)?;
+        Ok(Self::new(client))
+    }
+
+    /// Creates a context with default settings routing every request through `proxy`.
+    ///
+    /// # Errors
+    /// Returns [Error::Client] if the HTTP client cannot be created
+    /// or the proxy URL is invalid.
+    #[instrument(skip(proxy))]
+    pub fn with_proxy(proxy: &ProxyConfig) -> Result<Self> {
+        let client = default_client()
+            .proxy(proxy.build()?)
+            .build()
+            .map_err(Error::Client)?;
+        Ok(Self::new(client))
+    }
+
+    /// Returns the crate-level context, creating it on first use.
+    ///
+    /// # Errors
+    /// Returns [Error::Client] if the HTTP client cannot be created.
+    /// The next call tries again.
+    pub fn shared() -> Result<Self> {
+        if let Some(context) = SHARED.get() {
+            return Ok(context.clone());
+        }
+        debug!("Create shared context");
+        let context = Self::try_default()?;
+        Ok(SHARED.get_or_init(|| context).clone())
+    }
+
+    /// Transport of the context, including its middleware.
+    pub fn transport(&self) -> &dyn Transport {
+        &self.chain
+    }
+}
+
+fn default_client() -> reqwest::ClientBuilder {
+    reqwest::Client::builder()
+        .timeout(DEFAULT_TIMEOUT)
+        .pool_idle_timeout(DEFAULT_POOL_IDLE_TIMEOUT)
+        .pool_max_idle_per_host(DEFAULT_POOL_MAX_IDLE)
+}
@@ -0,0 +1,314 @@
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    agent::Agent,
+    download::{
+        manager::{DownloadManager, Saved},
+        DownloadStream,
+    },
+    error::Error,
+    image::Image,
+    result::Result,
+};
+use futures_util::{stream, StreamExt};
+use tokio::sync::watch;
+use tracing::{info, instrument, warn};
+
+/// Concurrency used by [BulkDownloader::new].
+pub const DEFAULT_CONCURRENCY: usize = 4;
+
+#[derive(Debug, Clone)]
+/// Cancels a [BulkDownloader] run from anywhere, clones share the state.
+///
+/// Files not started yet fail with [Error::Cancelled], running downloads stop
+/// at their next chunk and their temporary files are removed.
+pub struct CancelToken {
+    sender: Arc<watch::Sender<bool>>,
+}
+
+impl Default for CancelToken {
+    fn default() -> Self {
+        Self {
+            sender: Arc::new(watch::Sender::new(false)),
+        }
+    }
+}
+
+impl CancelToken {
+    /// Creates a token which is not cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels every run using this token.
+    pub fn cancel(&self) {
+        self.sender.send_replace(true);
+    }
+
+    /// Whether [CancelToken::cancel] was called.
+    pub fn is_cancelled(&self) -> bool {
+        *self.sender.borrow()
+    }
+
+    /// Waits until the token is cancelled.
+    pub async fn cancelled(&self) {
+        let mut receiver = self.sender.subscribe();
+        // The sender lives as long as `self`, so waiting cannot fail.
+        let _ = receiver.wait_for(|cancelled| *cancelled).await;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+/// Counters of a [BulkDownloader] run.
+pub struct Summary {
+    /// Files in the run.
+    pub total: usize,
+    /// Files downloaded and written.
+    pub saved: usize,
+    /// Files which were already present.
+    pub skipped: usize,
+    /// Files which failed, including cancelled ones.
+    pub failed: usize,
+    /// Bytes written.
+    pub bytes: u64,
+}
+
+impl Summary {
+    /// Files which are done, whatever the outcome.
+    pub fn finished(&self) -> usize {
+        self.saved + self.skipped + self.failed
+    }
+}
+
+#[derive(Debug)]
+/// Event reported to [BulkDownloader::on_progress].
+pub enum Progress<'a> {
+    /// A download started.
+    Started { url: &'a str },
+    /// A chunk of a file was written, `read` counts resumed bytes too
+    /// and `total` is the announced length of the whole file.
+    Received {
+        url: &'a str,
+        read: u64,
+        total: Option<u64>,
+    },
+    /// A file was saved or skipped.
+    Saved { url: &'a str, saved: &'a Saved },
+    /// A file failed.
+    Failed { url: &'a str, error: &'a Error },
+    /// Counters after a file finished.
+    Overall(Summary),
+}
+
+#[derive(Debug, Default)]
+/// Outcome of a [BulkDownloader] run, files are listed in the order they finished.
+pub struct Report {
+    pub saved: Vec<(Image, Saved)>,
+    pub failed: Vec<(Image, Error)>,
+    pub summary: Summary,
+}
+
+impl Report {
+    /// Whether every file was saved or skipped.
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+type Callback = dyn Fn(Progress<'_>) + Send + Sync;
+
+#[derive(Clone)]
+/// Saves many images at once through a [DownloadManager].
+///
+/// At most [BulkDownloader::concurrency] files are downloaded at the same time.
+/// Failures do not stop the run, they are collected in the [Report].
+///
+/// # Example
+/// ```rust
+/// use anime_grubber::agent::Agent;
+/// use anime_grubber::agents::waifu_pics::{Waifu, Categories, SFW};
+/// use anime_grubber::download::{bulk::{BulkDownloader, Progress}, manager::DownloadManager};
+///
+/// async fn example() -> anime_grubber::Result<()> {
+///     let waifu = Waifu::new(Categories::SFW(SFW::Hug));
+///     let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+///     let bulk = BulkDownloader::new(DownloadManager::new("archive", waifu.downloader()))
+///         .concurrency(8)
+///         .on_progress(move |event| {
+///             if let Progress::Overall(summary) = event {
+///                 let _ = sender.send(summary);
+///             }
+///         });
+///     tokio::spawn(async move {
+///         while let Some(summary) = receiver.recv().await {
+///             println!("{}/{}", summary.finished(), summary.total);
+///         }
+///     });
+///     let report = bulk.run(waifu.get_many().await?.into_vec()).await;
+///     for (image, error) in &report.failed {
+///         eprintln!("{image}: {error}");
+///     }
+///     Ok(())
+/// }
+/// ```
+pub struct BulkDownloader {
+    manager: DownloadManager,
+    concurrency: usize,
+    on_progress: Option<Arc<Callback>>,
+    cancel: CancelToken,
+}
+
+impl fmt::Debug for BulkDownloader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BulkDownloader")
+            .field("manager", &self.manager)
+            .field("concurrency", &self.concurrency)
+            .field("on_progress", &self.on_progress.is_some())
+            .field("cancel", &self.cancel)
+            .finish()
+    }
+}
+
+impl BulkDownloader {
+    /// Saves through `manager`, [DEFAULT_CONCURRENCY] files at a time.
+    pub fn new(manager: DownloadManager) -> Self {
+        Self {
+            manager,
+            concurrency: DEFAULT_CONCURRENCY,
+            on_progress: None,
+            cancel: CancelToken::new(),
+        }
+    }
+
+    /// Sets how many files are downloaded at the same time, at least one.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Calls `callback` for every [Progress] event.
+    ///
+    /// The callback runs inside the download, so it should return quickly,
+    /// e.g. by sending the event into a channel.
+    pub fn on_progress<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(Progress<'_>) + Send + Sync + 'static,
+    {
+        self.on_progress = Some(Arc::new(callback));
+        self
+    }
+
+    /// Uses `cancel` to stop runs instead of the own token.
+    pub fn with_cancel_token(mut self, cancel: CancelToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Returns the token cancelling runs of this downloader.
+    pub fn cancel_token(&self) -> CancelToken {
+        self.cancel.clone()
+    }
+
+    /// Returns the manager saving the files.
+    pub fn manager(&self) -> &DownloadManager {
+        &self.manager
+    }
+
+    /// Saves every image, returning what happened to each of them.
+    #[instrument(skip_all, fields(concurrency = self.concurrency))]
+    pub async fn run(&self, images: impl IntoIterator<Item = Image>) -> Report {
+        let images: Vec<Image> = images.into_iter().collect();
+        let summary = Mutex::new(Summary {
+            total: images.len(),
+            ..Summary::default()
+        });
+        let results: Vec<(Image, Result<Saved>)> = stream::iter(images)
+            .map(|image| async {
+                let result = self.save(&image).await;
+                let overall = {
+                    let mut summary = summary.lock().expect("poisoned summary");
+                    match &result {
+                        Ok(Saved::Written { bytes, .. }) => {
+                            summary.saved += 1;
+                            summary.bytes += bytes;
+                        }
+                        Ok(Saved::Skipped { .. }) => summary.skipped += 1,
+                        Err(_) => summary.failed += 1,
+                    }
+                    *summary
+                };
+                match &result {
+                    Ok(saved) => self.emit(Progress::Saved {
+                        url: &image.url,
+                        saved,
+                    }),
+                    Err(error) => {
+                        warn!("Failed to save {}: {error}", image.url);
+                        self.emit(Progress::Failed {
+                            url: &image.url,
+                            error,
+                        })
+                    }
+                }
+                self.emit(Progress::Overall(overall));
+                (image, result)
+            })
+            .buffer_unordered(self.concurrency)
+            .collect()
+            .await;
+
+        let mut report = Report {
+            summary: summary.into_inner().expect("poisoned summary"),
+            ..Report::default()
+        };
+        for (image, result) in results {
+            match result {
+                Ok(saved) => report.saved.push((image, saved)),
+                Err(error) => report.failed.push((image, error)),
+            }
+        }
+        info!(
+            "Saved {}, skipped {}, failed {}",
+            report.summary.saved, report.summary.skipped, report.summary.failed
+        );
+        report
+    }
+
+    /// Fetches a batch with [Agent::get_many] and saves every image of it.
+    ///
+    /// # Errors
+    /// Returns error only if the batch cannot be fetched.
+    pub async fn fetch_and_run<A>(&self, agent: &A) -> Result<Report>
+    where
+        A: Agent + ?Sized,
+    {
+        let images = agent.get_many().await?;
+        Ok(self.run(images.into_vec()).await)
+    }
+
+    async fn save(&self, image: &Image) -> Result<Saved> {
+        if self.cancel.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+        self.emit(Progress::Started { url: &image.url });
+        let on_chunk = |stream: &DownloadStream| {
+            self.emit(Progress::Received {
+                url: stream.url(),
+                read: stream.offset() + stream.read(),
+                total: stream.total_length(),
+            })
+        };
+        self.manager
+            .save_with(image, Some(&self.cancel), &on_chunk)
+            .await
+    }
+
+    fn emit(&self, event: Progress<'_>) {
+        if let Some(callback) = &self.on_progress {
+            callback(event);
+        }
+    }
+}
@@ -0,0 +1,631 @@
+use std::{
+    collections::HashSet,
+    fmt,
+    future::Future,
+    io::ErrorKind,
+    path::{Component, Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    agent::Agent,
+    download::{bulk::CancelToken, DownloadStream, Downloader},
+    error::Error,
+    image::Image,
+    layers::retry::RetryPolicy,
+    result::Result,
+};
+use futures_util::StreamExt;
+use tokio::{fs, io::AsyncWriteExt};
+use tracing::{debug, info, instrument, warn};
+
+/// Template used by [DownloadManager::new].
+pub const DEFAULT_TEMPLATE: &str = "{agent}/{category}/{aspect}/{filename}";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+/// What [DownloadManager] does when the target file already exists.
+pub enum Collision {
+    /// Keep the existing file and report the image as [Saved::Skipped].
+    #[default]
+    Skip,
+    /// Save under a free name, `abcd-1.png`, `abcd-2.png` and so on.
+    Rename,
+    /// Replace the existing file. A file another save is writing right now
+    /// is not downloaded twice but reported as [Saved::Skipped].
+    Overwrite,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Outcome of [DownloadManager::save].
+pub enum Saved {
+    /// The file was downloaded and written.
+    Written { path: PathBuf, bytes: u64 },
+    /// The file was already present.
+    Skipped { path: PathBuf },
+}
+
+impl Saved {
+    /// Path of the file on disk.
+    pub fn path(&self) -> &Path {
+        match self {
+            Saved::Written { path, .. } | Saved::Skipped { path } => path,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Saves images into a directory, sorted by a path template.
+///
+/// The template is a `/` separated relative path with placeholders:
+/// - `{agent}`: [Image::agent], e.g. `waifu_pics`
+/// - `{category}`: top-level category, e.g. `sfw`
+/// - `{aspect}`: nested category, e.g. `hug`
+/// - `{filename}`: [Image::file_name], e.g. `abcd.gif`
+/// - `{stem}` and `{ext}`: file name without and only its extension
+/// - `{kind}`: [MediaKind](crate::image::MediaKind) of the image, e.g. `animated`
+///
+/// Substituted values never create extra directories or leave the root.
+/// If the file name renders empty, e.g. for URLs ending in `/`, a name
+/// derived from the URL like `image-5f0c9a3e1b2d4c68` is used instead.
+/// Files are written to a temporary file next to the target and moved there
+/// once complete, so the target never holds a partial download.
+///
+/// Target paths are reserved while a file is saved, so concurrent saves of
+/// the same path, e.g. by a [BulkDownloader](crate::download::bulk::BulkDownloader),
+/// never write into each other. Clones of a manager share the reservations.
+/// Except with [Collision::Overwrite] the final move never replaces a file,
+/// even one created meanwhile by another program.
+///
+/// With [DownloadManager::resume] the partial file is kept when a download
+/// fails and the next attempt, e.g. by [DownloadManager::retry_policy],
+/// continues it with a `Range` request.
+///
+/// # Example
+/// ```rust
+/// use anime_grubber::agents::waifu_pics::{Waifu, Categories, SFW};
+/// use anime_grubber::download::manager::{Collision, DownloadManager};
+///
+/// async fn example() -> anime_grubber::Result<()> {
+///     let waifu = Waifu::new(Categories::SFW(SFW::Hug));
+///     let manager = DownloadManager::new("archive", waifu.downloader())
+///         .template("{category}/{aspect}/{filename}")
+///         .on_collision(Collision::Rename);
+///     for saved in manager.fetch_and_save(&waifu).await? {
+///         println!("{}", saved.path().display());
+///     }
+///     Ok(())
+/// }
+/// ```
+pub struct DownloadManager {
+    root: PathBuf,
+    downloader: Downloader,
+    template: String,
+    collision: Collision,
+    resume: bool,
+    hard_links: bool,
+    retry: RetryPolicy,
+    reserved: Arc<Mutex<HashSet<PathBuf>>>,
+}
+
+impl DownloadManager {
+    /// Saves into `root` using [DEFAULT_TEMPLATE] and skipping present files.
+    pub fn new(root: impl Into<PathBuf>, downloader: Downloader) -> Self {
+        Self {
+            root: root.into(),
+            downloader,
+            template: DEFAULT_TEMPLATE.to_owned(),
+            collision: Collision::default(),
+            resume: false,
+            hard_links: true,
+            retry: RetryPolicy::never(),
+            reserved: Arc::default(),
+        }
+    }
+
+    /// Sets the path template, see [DownloadManager].
+    pub fn template(mut self, template: impl Into<String>) -> Self {
+        self.template = template.into();
+        self
+    }
+
+    /// Sets what happens when the target file already exists.
+    pub fn on_collision(mut self, collision: Collision) -> Self {
+        self.collision = collision;
+        self
+    }
+
+    /// Keeps partial files of failed downloads and continues them next time.
+    ///
+    /// A partial file `.{name}.part` is stored next to the target together with
+    /// the `ETag`, `Last-Modified` date and length of the file in
+    /// `.{name}.part.meta`. The `ETag`, or the date if there is no strong
+    /// `ETag`, is sent as `If-Range`, so a file which has changed on the
+    /// server is downloaded again from the start, as is one whose length
+    /// changed. Without either validator the partial file cannot be trusted
+    /// and is discarded. Servers ignoring `Range` requests send the whole file,
+    /// which then replaces the partial one.
+    pub fn resume(mut self, resume: bool) -> Self {
+        self.resume = resume;
+        self
+    }
+
+    /// Whether finished files are moved into place by hard linking them.
+    ///
+    /// A hard link fails if the target exists, which makes it the safest way
+    /// not to replace files. Where linking fails, e.g. on FAT drives or many
+    /// network shares, the target is created empty first and then replaced by
+    /// renaming; turning hard links off skips the failing attempt.
+    pub fn hard_links(mut self, hard_links: bool) -> Self {
+        self.hard_links = hard_links;
+        self
+    }
+
+    /// Sets how failed downloads are repeated, by default they are not.
+    ///
+    /// Combined with [DownloadManager::resume] a repeated download continues
+    /// where the failed one stopped.
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry = policy;
+        self
+    }
+
+    /// Directory files are saved into.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Returns the downloader fetching the files.
+    pub fn downloader(&self) -> &Downloader {
+        &self.downloader
+    }
+
+    /// Path the template gives for `image`, before collisions are handled.
+    ///
+    /// # Example
+    /// ```rust
+    /// use std::path::Path;
+    /// use anime_grubber::agents::waifu_pics::Waifu;
+    /// use anime_grubber::download::manager::DownloadManager;
+    /// use anime_grubber::image::Image;
+    ///
+    /// let manager = DownloadManager::new("archive", Waifu::default().downloader());
+    /// let image = Image::new("https://i.waifu.pics/abcd.gif", "waifu_pics", "sfw").with_aspect("hug");
+    /// assert_eq!(manager.path_for(&image), Path::new("archive/waifu_pics/sfw/hug/abcd.gif"));
+    /// ```
+    pub fn path_for(&self, image: &Image) -> PathBuf {
+        let mut path = self.root.clone();
+        let mut parts = self.template.split('/').peekable();
+        while let Some(part) = parts.next() {
+            let part = render(part, image);
+            if parts.peek().is_none() && matches!(part.as_str(), "" | "." | "..") {
+                path.push(fallback_name(&image.url));
+            } else if !part.is_empty() {
+                path.push(part);
+            }
+        }
+        path
+    }
+
+    /// Downloads `image` into the directory.
+    ///
+    /// # Errors
+    /// - [Error::Io] if the file cannot be written
+    /// - [Error::Incomplete] if the connection dropped before the whole file arrived
+    /// - errors of [Downloader::stream]
+    #[instrument(skip(self, image), fields(url = %image.url))]
+    pub async fn save(&self, image: &Image) -> Result<Saved> {
+        self.save_with(image, None, &|_| {}).await
+    }
+
+    /// [DownloadManager::save] reporting every chunk and stopping once `cancel` fires.
+    pub(crate) async fn save_with(
+        &self,
+        image: &Image,
+        cancel: Option<&CancelToken>,
+        on_chunk: &(dyn Fn(&DownloadStream) + Send + Sync),
+    ) -> Result<Saved> {
+        let path = self.path_for(image);
+        let Some(reservation) = self.reserve(&path).await? else {
+            debug!("Skip present {}", path.display());
+            return Ok(Saved::Skipped { path });
+        };
+        if let Some(parent) = reservation.path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        let saved = self
+            .retry
+            .run(|| async {
+                if self.resume {
+                    self.resume_write(image, &reservation.path, cancel, on_chunk)
+                        .await
+                } else {
+                    self.write(image, &reservation.path, cancel, on_chunk).await
+                }
+            })
+            .await?;
+        if let Saved::Written { path, bytes } = &saved {
+            info!("Saved {} bytes to {}", bytes, path.display());
+        }
+        Ok(saved)
+    }
+
+    /// Reserves the path `image` is saved to, `None` if it is skipped.
+    async fn reserve(&self, path: &Path) -> Result<Option<Reservation>> {
+        let mut candidate = path.to_owned();
+        for n in 1.. {
+            let present = fs::try_exists(&candidate).await?;
+            if !present || self.collision == Collision::Overwrite {
+                if let Some(reservation) = Reservation::new(&self.reserved, &candidate) {
+                    if present {
+                        debug!("Overwrite {}", candidate.display());
+                    } else if n > 1 {
+                        debug!("Rename to {}", candidate.display());
+                    }
+                    return Ok(Some(reservation));
+                }
+            }
+            if self.collision != Collision::Rename {
+                return Ok(None);
+            }
+            candidate = numbered(path, n);
+        }
+        unreachable!("ran out of file names")
+    }
+
+    /// Moves the complete file `temp` to `path`.
+    ///
+    /// Unless overwriting, a file which appeared at `path` in the meantime is kept:
+    /// the image is skipped or moved to the next free name.
+    async fn persist(&self, temp: &Path, path: &Path, bytes: u64) -> Result<Saved> {
+        if self.collision == Collision::Overwrite {
+            fs::rename(temp, path).await?;
+            return Ok(Saved::Written {
+                path: path.to_owned(),
+                bytes,
+            });
+        }
+        let mut renamed = None;
+        loop {
+            let target = renamed.as_ref().map_or(path, |r: &Reservation| &r.path);
+            if self.move_new(temp, target).await? {
+                return Ok(Saved::Written {
+                    path: target.to_owned(),
+                    bytes,
+                });
+            }
+            debug!("{} appeared meanwhile", target.display());
+            if self.collision == Collision::Skip {
+                remove(temp).await;
+                return Ok(Saved::Skipped {
+                    path: target.to_owned(),
+                });
+            }
+            renamed = self.reserve(path).await?;
+        }
+    }
+
+    /// Moves `temp` to `target` unless `target` exists, returns whether it did.
+    async fn move_new(&self, temp: &Path, target: &Path) -> Result<bool> {
+        if self.hard_links {
+            match fs::hard_link(temp, target).await {
+                Ok(()) => {
+                    remove(temp).await;
+                    return Ok(true);
+                }
+                Err(error) if error.kind() == ErrorKind::AlreadyExists => return Ok(false),
+                Err(error) => debug!("Cannot link {}, {error}", target.display()),
+            }
+        }
+        // Claiming the name first keeps the rename from replacing a file which appeared.
+        match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(target)
+            .await
+        {
+            Ok(_) => {}
+            Err(error) if error.kind() == ErrorKind::AlreadyExists => return Ok(false),
+            Err(error) => return Err(error.into()),
+        }
+        if let Err(error) = fs::rename(temp, target).await {
+            remove(target).await;
+            return Err(error.into());
+        }
+        Ok(true)
+    }
+
+    /// Fetches a batch with [Agent::get_many] and saves every image of it.
+    ///
+    /// # Errors
+    /// Returns the first error, images saved before it stay on disk.
+    pub async fn fetch_and_save<A>(&self, agent: &A) -> Result<Vec<Saved>>
+    where
+        A: Agent + ?Sized,
+    {
+        let images = agent.get_many().await?;
+        let mut saved = Vec::with_capacity(images.len());
+        for image in images.iter() {
+            saved.push(self.save(image).await?);
+        }
+        Ok(saved)
+    }
+
+    /// Streams the file into a temporary file and moves it to `path`.
+    async fn write(
+        &self,
+        image: &Image,
+        path: &Path,
+        cancel: Option<&CancelToken>,
+        on_chunk: &(dyn Fn(&DownloadStream) + Send + Sync),
+    ) -> Result<Saved> {
+        let temp = temp_path(path);
+        let result = async {
+            let mut stream = cancellable(cancel, self.downloader.stream(&image.url)).await?;
+            let mut file = fs::File::create(&temp).await?;
+            copy(&mut stream, &mut file, cancel, on_chunk).await?;
+            self.persist(&temp, path, stream.read()).await
+        }
+        .await;
+        if result.is_err() {
+            remove(&temp).await;
+        }
+        result
+    }
+
+    /// Continues the partial file of `path` if present and moves it to `path` once complete.
+    ///
+    /// The partial file is kept on errors.
+    async fn resume_write(
+        &self,
+        image: &Image,
+        path: &Path,
+        cancel: Option<&CancelToken>,
+        on_chunk: &(dyn Fn(&DownloadStream) + Send + Sync),
+    ) -> Result<Saved> {
+        let (part, meta_path) = part_paths(path);
+        let mut offset = match fs::metadata(&part).await {
+            Ok(metadata) => metadata.len(),
+            Err(error) if error.kind() == ErrorKind::NotFound => 0,
+            Err(error) => return Err(error.into()),
+        };
+        let meta = match offset {
+            0 => PartMeta::default(),
+            _ => PartMeta::parse(&fs::read_to_string(&meta_path).await.unwrap_or_default()),
+        };
+        if offset > 0 && meta.validator().is_none() {
+            debug!("No validator for {}, restart", part.display());
+            offset = 0;
+        }
+        let mut stream = cancellable(
+            cancel,
+            self.downloader
+                .stream_from(&image.url, offset, meta.validator()),
+        )
+        .await?;
+        if stream.offset() > 0 {
+            if let (Some(stored), Some(total)) = (meta.total_length, stream.total_length()) {
+                if stored != total {
+                    debug!("Length changed from {stored} to {total}, restart");
+                    stream = cancellable(cancel, self.downloader.stream(&image.url)).await?;
+                }
+            }
+        }
+
+        let mut file = if stream.offset() > 0 {
+            debug!("Resume {} at {}", part.display(), stream.offset());
+            fs::OpenOptions::new().append(true).open(&part).await?
+        } else {
+            if offset > 0 {
+                debug!("Restart {}", part.display());
+            }
+            fs::write(&meta_path, PartMeta::of(&stream).to_string()).await?;
+            fs::File::create(&part).await?
+        };
+        copy(&mut stream, &mut file, cancel, on_chunk).await?;
+        let saved = self
+            .persist(&part, path, stream.offset() + stream.read())
+            .await?;
+        remove(&meta_path).await;
+        Ok(saved)
+    }
+}
+
+/// Writes the chunks of `stream` into `file` and checks that the whole file arrived.
+async fn copy(
+    stream: &mut DownloadStream,
+    file: &mut fs::File,
+    cancel: Option<&CancelToken>,
+    on_chunk: &(dyn Fn(&DownloadStream) + Send + Sync),
+) -> Result<()> {
+    let result: Result<()> = async {
+        while let Some(chunk) = cancellable(cancel, async { Ok(stream.next().await) }).await? {
+            file.write_all(&chunk?).await?;
+            on_chunk(stream);
+        }
+        Ok(())
+    }
+    .await;
+    // Written chunks reach the disk even on errors, so a resumed download continues after them.
+    file.sync_all().await?;
+    result?;
+    stream.check_complete()
+}
+
+/// Runs `future` unless `cancel` fires first.
+async fn cancellable<T>(
+    cancel: Option<&CancelToken>,
+    future: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    match cancel {
+        Some(cancel) => tokio::select! {
+            result = future => result,
+            () = cancel.cancelled() => Err(Error::Cancelled),
+        },
+        None => future.await,
+    }
+}
+
+/// Replaces the placeholders of one template component.
+fn render(part: &str, image: &Image) -> String {
+    let file_name = image.file_name();
+    let (stem, ext) = file_name.rsplit_once('.').unwrap_or((file_name, ""));
+    let mut rendered = String::new();
+    let mut rest = part;
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find('}').map(|end| start + end) else {
+            rest = &rest[start..];
+            break;
+        };
+        let value = match &rest[start + 1..end] {
+            "agent" => image.agent.to_owned(),
+            "category" => image.category.to_lowercase(),
+            "aspect" => image.aspect.as_deref().unwrap_or_default().to_lowercase(),
+            "filename" => file_name.to_owned(),
+            "stem" => stem.to_owned(),
+            "ext" => ext.to_owned(),
+            "kind" => <&str>::from(&image.kind).to_lowercase(),
+            _ => rest[start..=end].to_owned(),
+        };
+        rendered.push_str(&sanitize(&value));
+        rest = &rest[end + 1..];
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+/// File name for images whose template gives none, stable across runs
+/// so that [Collision::Skip] still recognises the file.
+fn fallback_name(url: &str) -> String {
+    // FNV-1a, the std hasher may change between releases.
+    let hash = url.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    });
+    format!("image-{hash:016x}")
+}
+
+/// Keeps a substituted value inside its path component.
+fn sanitize(value: &str) -> String {
+    let value = value.replace(['/', '\\', '\0'], "_");
+    match Path::new(&value).components().next() {
+        Some(Component::Normal(_)) | None => value,
+        _ => value.replace('.', "_"),
+    }
+}
+
+/// `stem-N.ext` next to `path`.
+fn numbered(path: &Path, n: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{stem}-{n}.{}", ext.to_string_lossy()),
+        None => format!("{stem}-{n}"),
+    };
+    path.with_file_name(name)
+}
+
+/// Path reserved by one save until it is dropped.
+struct Reservation {
+    reserved: Arc<Mutex<HashSet<PathBuf>>>,
+    path: PathBuf,
+}
+
+impl Reservation {
+    /// Reserves `path` unless another save holds it.
+    fn new(reserved: &Arc<Mutex<HashSet<PathBuf>>>, path: &Path) -> Option<Self> {
+        let inserted = reserved
+            .lock()
+            .expect("poisoned reservations")
+            .insert(path.to_owned());
+        inserted.then(|| Self {
+            reserved: reserved.clone(),
+            path: path.to_owned(),
+        })
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        if let Ok(mut reserved) = self.reserved.lock() {
+            reserved.remove(&self.path);
+        }
+    }
+}
+
+/// Removes a leftover file, logging failures other than a missing file.
+async fn remove(path: &Path) {
+    if let Err(error) = fs::remove_file(path).await {
+        if error.kind() != ErrorKind::NotFound {
+            warn!("Failed to remove {}: {error}", path.display());
+        }
+    }
+}
+
+/// Hidden partial file next to `path` and the file holding its [PartMeta].
+fn part_paths(path: &Path) -> (PathBuf, PathBuf) {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    (
+        path.with_file_name(format!(".{name}.part")),
+        path.with_file_name(format!(".{name}.part.meta")),
+    )
+}
+
+#[derive(Debug, Default)]
+/// What is known about the file a partial file belongs to, stored as
+/// `name: value` lines.
+struct PartMeta {
+    etag: Option<String>,
+    last_modified: Option<String>,
+    total_length: Option<u64>,
+}
+
+impl PartMeta {
+    fn of(stream: &DownloadStream) -> Self {
+        Self {
+            etag: stream.etag().map(str::to_owned),
+            last_modified: stream.last_modified().map(str::to_owned),
+            total_length: stream.total_length(),
+        }
+    }
+
+    fn parse(text: &str) -> Self {
+        let mut meta = Self::default();
+        for (name, value) in text.lines().filter_map(|line| line.split_once(": ")) {
+            match name {
+                "etag" => meta.etag = Some(value.to_owned()),
+                "last-modified" => meta.last_modified = Some(value.to_owned()),
+                "length" => meta.total_length = value.parse().ok(),
+                _ => {}
+            }
+        }
+        meta
+    }
+
+    /// Value for `If-Range`, which only accepts strong `ETag`s.
+    fn validator(&self) -> Option<&str> {
+        self.etag
+            .as_deref()
+            .filter(|etag| !etag.starts_with("W/"))
+            .or(self.last_modified.as_deref())
+    }
+}
+
+impl fmt::Display for PartMeta {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(etag) = &self.etag {
+            writeln!(f, "etag: {etag}")?;
+        }
+        if let Some(last_modified) = &self.last_modified {
+            writeln!(f, "last-modified: {last_modified}")?;
+        }
+        if let Some(total_length) = self.total_length {
+            writeln!(f, "length: {total_length}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Hidden temporary file next to `path`.
+fn temp_path(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{name}.{:08x}.tmp", fastrand::u32(..)))
+}
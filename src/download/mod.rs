@@ -0,0 +1,417 @@
+/// Downloading many files at once
+pub mod bulk;
+/// Saving downloads to disk
+pub mod manager;
+/// Detecting file types from their content
+pub mod sniff;
+
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::{
+    context::AgentContext,
+    error::Error,
+    result::Result,
+    status::error_for_streaming_status,
+    transport::{ByteStream, Request},
+};
+use bytes::{Bytes, BytesMut};
+use futures_util::{stream, Stream, StreamExt};
+use reqwest::{
+    header::{
+        HeaderMap, HeaderValue, CONTENT_RANGE, CONTENT_TYPE, ETAG, IF_RANGE, LAST_MODIFIED, RANGE,
+    },
+    StatusCode,
+};
+use sniff::{FileType, SNIFF_LEN};
+use tracing::{debug, instrument, warn};
+
+/// Default limit of [Downloader::max_size], 64 MiB.
+pub const DEFAULT_MAX_SIZE: u64 = 64 * 1024 * 1024;
+
+#[derive(Debug, Clone)]
+/// Downloads the files behind image URLs.
+///
+/// Requests go through the transport and middleware of an [AgentContext],
+/// usually the one of the agent which returned the images, see
+/// [Waifu::downloader](crate::agents::waifu_pics::Waifu::downloader).
+/// Files larger than [Downloader::max_size] fail with [Error::TooLarge].
+///
+/// # Example
+/// ```rust
+/// use anime_grubber::agent::Agent;
+/// use anime_grubber::agents::waifu_pics::{Waifu, Categories, SFW};
+///
+/// async fn example() -> anime_grubber::Result<()> {
+///     let waifu = Waifu::new(Categories::SFW(SFW::Hug));
+///     let image = waifu.get().await?;
+///     let file = waifu.downloader().max_size(8 * 1024 * 1024).download(&image).await?;
+///     println!("{} bytes of {:?}", file.bytes.len(), file.content_type);
+///     Ok(())
+/// }
+/// ```
+pub struct Downloader {
+    context: AgentContext,
+    max_size: Option<u64>,
+    verify_content: bool,
+}
+
+impl Downloader {
+    /// Creates a downloader limited to [DEFAULT_MAX_SIZE] bytes per file.
+    pub fn new(context: AgentContext) -> Self {
+        Self {
+            context,
+            max_size: Some(DEFAULT_MAX_SIZE),
+            verify_content: false,
+        }
+    }
+
+    /// Sets the maximum size of a file in bytes.
+    pub fn max_size(mut self, max_size: u64) -> Self {
+        self.max_size = Some(max_size);
+        self
+    }
+
+    /// Removes the size limit.
+    pub fn unlimited(mut self) -> Self {
+        self.max_size = None;
+        self
+    }
+
+    /// Checks the magic bytes of every file against its URL extension and
+    /// `Content-Type`, see [sniff::verify].
+    ///
+    /// Catches e.g. HTML error pages served with status `200` in place of images.
+    /// Streams resumed by [Downloader::stream_from] are not checked, their
+    /// first bytes were received before.
+    pub fn verify_content(mut self, verify: bool) -> Self {
+        self.verify_content = verify;
+        self
+    }
+
+    /// Returns the context requests are sent through.
+    pub fn context(&self) -> &AgentContext {
+        &self.context
+    }
+
+    /// Downloads the whole file at `url`, e.g. of an [Image](crate::image::Image).
+    ///
+    /// # Errors
+    /// - [Error::TooLarge] if the file exceeds the size limit
+    /// - [Error::Incomplete] if the body is shorter than announced
+    /// - [Error::ContentMismatch] if [Downloader::verify_content] is on and the content
+    ///   is not what was promised
+    /// - errors of [error_for_streaming_status] for unsuccessful statuses
+    /// - transport errors
+    #[instrument(skip(self, url), fields(url = url.as_ref()))]
+    pub async fn download(&self, url: impl AsRef<str>) -> Result<Download> {
+        let mut stream = self.stream(url).await?;
+        let mut bytes = BytesMut::new();
+        while let Some(chunk) = stream.next().await {
+            bytes.extend_from_slice(&chunk?);
+        }
+        stream.check_complete()?;
+        debug!("Downloaded {} bytes", bytes.len());
+        Ok(Download {
+            url: stream.url,
+            content_type: stream.content_type,
+            content_length: bytes.len() as u64,
+            bytes: bytes.freeze(),
+        })
+    }
+
+    /// Starts downloading the file at `url`, returning its chunks as they arrive.
+    ///
+    /// # Errors
+    /// Same as [Downloader::download]. Once the stream is returned, [Error::TooLarge],
+    /// [Error::ContentMismatch] and transport errors are reported as its items.
+    pub async fn stream(&self, url: impl AsRef<str>) -> Result<DownloadStream> {
+        self.stream_from(url, 0, None).await
+    }
+
+    /// Continues a download at byte `offset` with a `Range` request.
+    ///
+    /// `if_range`, the `ETag` or `Last-Modified` date of the partial file, is
+    /// sent as `If-Range`, so a file which has changed since is sent whole.
+    /// The whole file is also requested when the server rejects the range,
+    /// unless it reports that the file is exactly `offset` bytes long. Then
+    /// the stream is empty, as nothing is missing. [DownloadStream::offset]
+    /// tells where the returned data starts: `offset` if the server honoured
+    /// the range, otherwise 0.
+    ///
+    /// # Errors
+    /// Same as [Downloader::stream], the size limit applies to the whole file.
+    #[instrument(skip(self, url, if_range), fields(url = url.as_ref()))]
+    pub async fn stream_from(
+        &self,
+        url: impl AsRef<str>,
+        offset: u64,
+        if_range: Option<&str>,
+    ) -> Result<DownloadStream> {
+        let url = url.as_ref();
+        let mut offset = offset;
+        let res = loop {
+            let mut request = Request::get(url);
+            if offset > 0 {
+                let range = HeaderValue::from_str(&format!("bytes={offset}-"))
+                    .expect("range header is ASCII");
+                request = request.header(RANGE, range);
+                if let Some(value) = if_range.and_then(|value| HeaderValue::from_str(value).ok()) {
+                    request = request.header(IF_RANGE, value);
+                }
+            }
+            let res = self.context.transport().send_streaming(request).await?;
+            if offset > 0 && res.status == StatusCode::RANGE_NOT_SATISFIABLE {
+                if unsatisfied_length(&res.headers) == Some(offset) {
+                    debug!("Already complete at {offset}");
+                    break res;
+                }
+                debug!("Range rejected, download whole file");
+                offset = 0;
+                continue;
+            }
+            let res = error_for_streaming_status(res).await?;
+            match res.status {
+                StatusCode::PARTIAL_CONTENT if offset > 0 => match content_range(&res.headers) {
+                    Some((start, _)) if start == offset => {
+                        debug!("Resume at {offset}");
+                        break res;
+                    }
+                    range => {
+                        warn!("Unexpected range {range:?}, download whole file");
+                        offset = 0;
+                    }
+                },
+                _ => {
+                    if offset > 0 {
+                        debug!("Range ignored, download whole file");
+                    }
+                    offset = 0;
+                    break res;
+                }
+            }
+        };
+
+        let complete = res.status == StatusCode::RANGE_NOT_SATISFIABLE;
+        let content_length = if complete {
+            Some(0)
+        } else {
+            res.content_length()
+        };
+        let total_length = match res.status {
+            StatusCode::PARTIAL_CONTENT => content_range(&res.headers)
+                .and_then(|(_, total)| total)
+                .or(content_length.map(|length| offset + length)),
+            StatusCode::RANGE_NOT_SATISFIABLE => Some(offset),
+            _ => content_length,
+        };
+        if let (Some(length), Some(limit)) = (total_length, self.max_size) {
+            if length > limit {
+                return Err(Error::TooLarge {
+                    url: url.to_owned(),
+                    limit,
+                });
+            }
+        }
+        let header = |name| {
+            res.headers
+                .get(name)
+                .and_then(|value: &HeaderValue| value.to_str().ok())
+                .map(str::to_owned)
+        };
+        Ok(DownloadStream {
+            url: url.to_owned(),
+            content_type: header(CONTENT_TYPE).filter(|_| !complete),
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+            content_length,
+            total_length,
+            offset,
+            body: if complete {
+                stream::empty().boxed()
+            } else {
+                res.body
+            },
+            limit: self.max_size,
+            head: (self.verify_content && offset == 0).then(BytesMut::new),
+            file_type: None,
+            read: 0,
+            done: false,
+        })
+    }
+}
+
+/// Parses `Content-Range: bytes start-end/total` into the start and the total, if known.
+fn content_range(headers: &HeaderMap) -> Option<(u64, Option<u64>)> {
+    let value = headers.get(CONTENT_RANGE)?.to_str().ok()?;
+    let (range, total) = value.strip_prefix("bytes ")?.split_once('/')?;
+    let (start, _) = range.split_once('-')?;
+    Some((start.trim().parse().ok()?, total.trim().parse().ok()))
+}
+
+/// Parses the file length of a `416` response, `Content-Range: bytes */total`.
+fn unsatisfied_length(headers: &HeaderMap) -> Option<u64> {
+    let value = headers.get(CONTENT_RANGE)?.to_str().ok()?;
+    value.strip_prefix("bytes */")?.trim().parse().ok()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A downloaded file.
+pub struct Download {
+    /// URL the file was requested from.
+    pub url: String,
+    pub bytes: Bytes,
+    /// `Content-Type` header of the response.
+    pub content_type: Option<String>,
+    /// Length of the file in bytes.
+    pub content_length: u64,
+}
+
+/// File being downloaded, a [Stream] of its chunks.
+///
+/// The stream ends after the first error.
+pub struct DownloadStream {
+    url: String,
+    content_type: Option<String>,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    content_length: Option<u64>,
+    total_length: Option<u64>,
+    offset: u64,
+    body: ByteStream,
+    limit: Option<u64>,
+    /// First bytes held back until the content is verified.
+    head: Option<BytesMut>,
+    file_type: Option<FileType>,
+    read: u64,
+    done: bool,
+}
+
+impl std::fmt::Debug for DownloadStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DownloadStream")
+            .field("url", &self.url)
+            .field("content_type", &self.content_type)
+            .field("content_length", &self.content_length)
+            .field("offset", &self.offset)
+            .field("file_type", &self.file_type)
+            .field("read", &self.read)
+            .finish_non_exhaustive()
+    }
+}
+
+impl DownloadStream {
+    /// URL the file is requested from.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// `Content-Type` header of the response.
+    pub fn content_type(&self) -> Option<&str> {
+        self.content_type.as_deref()
+    }
+
+    /// `ETag` header of the response.
+    pub fn etag(&self) -> Option<&str> {
+        self.etag.as_deref()
+    }
+
+    /// `Last-Modified` header of the response.
+    pub fn last_modified(&self) -> Option<&str> {
+        self.last_modified.as_deref()
+    }
+
+    /// Length announced in the `Content-Length` header, for ranges only the rest of the file.
+    pub fn content_length(&self) -> Option<u64> {
+        self.content_length
+    }
+
+    /// Length of the whole file, if the server announced it.
+    pub fn total_length(&self) -> Option<u64> {
+        self.total_length
+    }
+
+    /// Position in the file the data starts at, see [Downloader::stream_from].
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Type detected from the first bytes when [Downloader::verify_content] is on.
+    pub fn file_type(&self) -> Option<FileType> {
+        self.file_type
+    }
+
+    /// Bytes received so far, not counting the [DownloadStream::offset].
+    pub fn read(&self) -> u64 {
+        self.read
+    }
+
+    /// Checks that the whole file arrived.
+    ///
+    /// # Errors
+    /// Returns [Error::Incomplete] if fewer or more bytes than announced were received.
+    pub fn check_complete(&self) -> Result<()> {
+        match self.total_length {
+            Some(expected) if expected != self.offset + self.read => Err(Error::Incomplete {
+                url: self.url.clone(),
+                expected,
+                received: self.offset + self.read,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Ends the stream with `error`.
+    fn fail(&mut self, error: Error) -> Poll<Option<Result<Bytes>>> {
+        self.done = true;
+        Poll::Ready(Some(Err(error)))
+    }
+}
+
+impl Stream for DownloadStream {
+    type Item = Result<Bytes>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if this.done {
+                return Poll::Ready(None);
+            }
+            let chunk = match this.body.poll_next_unpin(cx) {
+                Poll::Ready(Some(Ok(chunk))) => Some(chunk),
+                Poll::Ready(Some(Err(error))) => return this.fail(error),
+                Poll::Ready(None) => {
+                    this.done = true;
+                    None
+                }
+                Poll::Pending => return Poll::Pending,
+            };
+            if let Some(chunk) = &chunk {
+                this.read += chunk.len() as u64;
+                if let Some(limit) = this.limit.filter(|limit| this.offset + this.read > *limit) {
+                    let url = this.url.clone();
+                    return this.fail(Error::TooLarge { url, limit });
+                }
+            }
+            let Some(head) = &mut this.head else {
+                return Poll::Ready(chunk.map(Ok));
+            };
+            if let Some(chunk) = &chunk {
+                head.extend_from_slice(chunk);
+                if head.len() < SNIFF_LEN {
+                    continue;
+                }
+            }
+            let head = this.head.take().unwrap_or_default().freeze();
+            match sniff::verify(&this.url, this.content_type.as_deref(), &head) {
+                Ok(file_type) => this.file_type = file_type,
+                Err(error) => return this.fail(error),
+            }
+            if !head.is_empty() {
+                return Poll::Ready(Some(Ok(head)));
+            }
+        }
+    }
+}
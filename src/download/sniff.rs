@@ -0,0 +1,172 @@
+use std::fmt;
+
+use crate::{error::Error, image::MediaKind, result::Result};
+
+/// Number of leading bytes [FileType::sniff] needs to tell all types apart.
+pub const SNIFF_LEN: usize = 12;
+
+/// Major brands of `ftyp` boxes which are not MP4 video: HEIF and AVIF
+/// images and image sequences, and QuickTime movies. Every other brand of
+/// the box is taken for MP4, as new ones keep appearing.
+const NON_MP4_BRANDS: [&[u8; 4]; 11] = [
+    b"heic", b"heix", b"heim", b"heis", b"hevc", b"hevx", b"mif1", b"msf1", b"avif", b"avis",
+    b"qt  ",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// Media file types recognized by their magic bytes.
+pub enum FileType {
+    Png,
+    Jpeg,
+    Gif,
+    WebP,
+    Mp4,
+    WebM,
+}
+
+impl FileType {
+    /// Detects the type from the first bytes of a file, see [SNIFF_LEN].
+    ///
+    /// # Example
+    /// ```rust
+    /// use anime_grubber::download::sniff::FileType;
+    ///
+    /// assert_eq!(FileType::sniff(b"GIF89a\x01\x00"), Some(FileType::Gif));
+    /// assert_eq!(FileType::sniff(b"<!DOCTYPE html>"), None);
+    /// ```
+    pub fn sniff(head: &[u8]) -> Option<Self> {
+        match head {
+            [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n', ..] => Some(Self::Png),
+            [0xff, 0xd8, 0xff, ..] => Some(Self::Jpeg),
+            [b'G', b'I', b'F', b'8', b'7' | b'9', b'a', ..] => Some(Self::Gif),
+            [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some(Self::WebP),
+            [_, _, _, _, b'f', b't', b'y', b'p', a, b, c, d, ..]
+                if !NON_MP4_BRANDS.contains(&&[*a, *b, *c, *d]) =>
+            {
+                Some(Self::Mp4)
+            }
+            [0x1a, 0x45, 0xdf, 0xa3, ..] => Some(Self::WebM),
+            _ => None,
+        }
+    }
+
+    /// Type a file extension stands for, `None` for extensions which are not sniffed.
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_lowercase().as_str() {
+            "png" | "apng" => Some(Self::Png),
+            "jpg" | "jpeg" | "jfif" => Some(Self::Jpeg),
+            "gif" => Some(Self::Gif),
+            "webp" => Some(Self::WebP),
+            "mp4" | "m4v" => Some(Self::Mp4),
+            "webm" => Some(Self::WebM),
+            _ => None,
+        }
+    }
+
+    /// Type a MIME type stands for, parameters like `; charset=utf-8` are ignored.
+    pub fn from_mime(mime: &str) -> Option<Self> {
+        match essence(mime).as_str() {
+            "image/png" | "image/apng" => Some(Self::Png),
+            "image/jpeg" | "image/jpg" | "image/pjpeg" => Some(Self::Jpeg),
+            "image/gif" => Some(Self::Gif),
+            "image/webp" => Some(Self::WebP),
+            "video/mp4" => Some(Self::Mp4),
+            "video/webm" => Some(Self::WebM),
+            _ => None,
+        }
+    }
+
+    /// MIME type of the file type.
+    pub fn mime(&self) -> &'static str {
+        match self {
+            Self::Png => "image/png",
+            Self::Jpeg => "image/jpeg",
+            Self::Gif => "image/gif",
+            Self::WebP => "image/webp",
+            Self::Mp4 => "video/mp4",
+            Self::WebM => "video/webm",
+        }
+    }
+
+    /// Usual file extension without the dot.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Jpeg => "jpg",
+            Self::Gif => "gif",
+            Self::WebP => "webp",
+            Self::Mp4 => "mp4",
+            Self::WebM => "webm",
+        }
+    }
+
+    /// Media kind of files of this type.
+    pub fn kind(&self) -> MediaKind {
+        match self {
+            Self::Png | Self::Jpeg | Self::WebP => MediaKind::Static,
+            Self::Gif => MediaKind::Animated,
+            Self::Mp4 | Self::WebM => MediaKind::Video,
+        }
+    }
+}
+
+impl fmt::Display for FileType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.mime())
+    }
+}
+
+/// Checks that the first bytes of the file at `url` match what its extension
+/// and `Content-Type` promise.
+///
+/// Extensions and MIME types which are not sniffed promise nothing, except
+/// `text/*` content types: those are error pages rather than media.
+///
+/// # Errors
+/// Returns [Error::ContentMismatch] naming the first broken promise.
+///
+/// # Example
+/// ```rust
+/// use anime_grubber::download::sniff::{verify, FileType};
+///
+/// let gif = b"GIF89a\x01\x00\x01\x00\x00\x00";
+/// assert_eq!(verify("https://i.waifu.pics/a.gif", Some("image/gif"), gif).unwrap(), Some(FileType::Gif));
+/// assert!(verify("https://i.waifu.pics/a.gif", Some("text/html"), b"<html>").is_err());
+/// ```
+pub fn verify(url: &str, content_type: Option<&str>, head: &[u8]) -> Result<Option<FileType>> {
+    let detected = FileType::sniff(head);
+    let mismatch = |expected: &str| Error::ContentMismatch {
+        url: url.to_owned(),
+        expected: expected.to_owned(),
+        detected: detected
+            .map_or("unrecognized content", |detected| detected.mime())
+            .to_owned(),
+    };
+    let extension = crate::image::extension_of(url);
+    if let Some(expected) = extension.as_deref().and_then(FileType::from_extension) {
+        if detected != Some(expected) {
+            return Err(mismatch(&format!(
+                ".{} file",
+                extension.unwrap_or_default()
+            )));
+        }
+    }
+    if let Some(content_type) = content_type {
+        let promised = FileType::from_mime(content_type);
+        if promised.is_some_and(|expected| detected != Some(expected))
+            || essence(content_type).starts_with("text/")
+        {
+            return Err(mismatch(content_type));
+        }
+    }
+    Ok(detected)
+}
+
+/// Lowercased MIME type without parameters.
+fn essence(mime: &str) -> String {
+    mime.split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_lowercase()
+}
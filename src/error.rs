@@ -1,3 +1,5 @@
+use std::{sync::Arc, time::Duration};
+
 use reqwest::StatusCode;
 use thiserror::Error;
 /// Represents the errors that can occur in the application.
@@ -8,20 +10,81 @@ use thiserror::Error;
 ///
 /// # Variants
 /// - `NotFound`: An error indicating that a requested resource was not found.
+/// - `RateLimit`: The source answered `429`, `retry_after` holds the parsed
+///   `Retry-After` header if there was one.
+/// - `RequestFailed`: Any other unsuccessful status together with the URL and
+///   the beginning of the response body.
 /// - `Reqwest`: An error originating from the `reqwest` crate, typically related
 ///   to HTTP requests.
 /// - `MiniSerde`: An error that occurs during deserialization using the
 ///   `miniserde` library.
+/// - `Client`: The HTTP client of an agent could not be created.
+/// - `UnknownAgent`: No agent is registered under the given name.
+/// - `UnknownCategory`: The string does not name a category.
+/// - `InvalidConfig`: A setting passed to a builder or constructor is out of range.
+/// - `CircuitOpen`: The agent failed too often and is not called until `retry_after` passes.
+/// - `TooLarge`: A downloaded file is larger than `limit` bytes.
+/// - `Incomplete`: A download ended before the announced length was received.
+/// - `ContentMismatch`: The content of a download is not what its URL or
+///   `Content-Type` promised, e.g. an HTML page saved as `.gif`.
+/// - `Io`: A file could not be read or written.
+/// - `Cancelled`: The operation was cancelled before it finished.
+/// - `Shared`: An error of a request whose result was handed to several callers,
+///   see [Error::root].
 #[derive(Error, Debug)]
 pub enum Error {
     #[error("Not found")]
     NotFound,
-    #[error("Too many requsts")]
-    RateLimit,
-    #[error("Request failed with status: {0}")]
-    RequestFailed(StatusCode),
+    #[error("Too many requsts, retry after {retry_after:?}")]
+    RateLimit { retry_after: Option<Duration> },
+    #[error("Request to {url} failed with status: {status}")]
+    RequestFailed {
+        status: StatusCode,
+        url: String,
+        body: String,
+    },
     #[error("Some reqwest trouble")]
     Reqwest(#[from] reqwest::Error),
     #[error("Desirialise error")]
     MiniSerde(#[from] miniserde::Error),
+    #[error("Failed to create HTTP client")]
+    Client(#[source] reqwest::Error),
+    #[error("Unknown agent: {0}")]
+    UnknownAgent(String),
+    #[error("Unknown category: {0}")]
+    UnknownCategory(String),
+    #[error("Invalid configuration: {0}")]
+    InvalidConfig(String),
+    #[error("Circuit breaker is open, retry after {retry_after:?}")]
+    CircuitOpen { retry_after: Duration },
+    #[error("File at {url} is larger than {limit} bytes")]
+    TooLarge { url: String, limit: u64 },
+    #[error("Download of {url} ended after {received} of {expected} bytes")]
+    Incomplete {
+        url: String,
+        expected: u64,
+        received: u64,
+    },
+    #[error("Expected {expected} at {url}, received {detected}")]
+    ContentMismatch {
+        url: String,
+        expected: String,
+        detected: String,
+    },
+    #[error("File system error")]
+    Io(#[from] std::io::Error),
+    #[error("Cancelled")]
+    Cancelled,
+    #[error(transparent)]
+    Shared(Arc<Error>),
+}
+
+impl Error {
+    /// Returns the underlying error, looking through [Error::Shared].
+    pub fn root(&self) -> &Error {
+        match self {
+            Error::Shared(error) => error.root(),
+            error => error,
+        }
+    }
 }
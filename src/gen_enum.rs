@@ -9,6 +9,9 @@
 /// - `From<&T> for &str`: returns current variant name
 /// - `nested_str()`: returns string representation one level down
 /// - `deepest_str()`: recursively gets the deepest nested variant name
+/// - `variants()`: lists every variant, nested enums are expanded
+/// - `path()`: lowercased variant names joined with `/`, e.g. `sfw/hug`
+/// - `FromStr`: parses the output of `path()`, ignoring case
 ///
 /// # Examples
 ///
@@ -21,6 +24,7 @@
 /// let variant = SimpleEnum::First;
 /// assert_eq!(<&str>::from(&variant), "First");
 /// assert_eq!(variant.deepest_str(), "First");
+/// assert_eq!(SimpleEnum::variants(), [SimpleEnum::First, SimpleEnum::Second, SimpleEnum::Third]);
 /// ```
 ///
 /// 2. Simple nested enum:
@@ -34,6 +38,9 @@
 /// assert_eq!(<&str>::from(&nested), "Value");  // Current variant
 /// assert_eq!(nested.nested_str(), "One");      // One level down
 /// assert_eq!(nested.deepest_str(), "One");     // Deepest level
+/// assert_eq!(Outer::variants(), [Outer::Value(Inner::One), Outer::Value(Inner::Two)]);
+/// assert_eq!(nested.path(), "value/one");
+/// assert_eq!("Value/Two".parse::<Outer>().unwrap(), Outer::Value(Inner::Two));
 /// ```
 ///
 /// 3. Deep nested example (5 levels):
@@ -78,7 +85,7 @@
 #[macro_export]
 macro_rules! gen_enum {
     ($name:tt, [ $($variant:ident),* $(,)? ]) => {
-        #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
         pub enum $name {
             $( $variant ),*
         }
@@ -106,10 +113,27 @@ macro_rules! gen_enum {
             pub fn deepest_str(&self) -> &str {
                 <&str>::from(self)
             }
+            pub fn variants() -> Vec<Self> {
+                vec![$( Self::$variant ),*]
+            }
+            pub fn path(&self) -> String {
+                <&str>::from(self).to_lowercase()
+            }
+        }
+        impl ::std::str::FromStr for $name {
+            type Err = $crate::error::Error;
+            fn from_str(s: &str) -> ::std::result::Result<Self, Self::Err> {
+                $(
+                    if s.eq_ignore_ascii_case(stringify!($variant)) {
+                        return Ok(Self::$variant);
+                    }
+                )*
+                Err($crate::error::Error::UnknownCategory(s.to_owned()))
+            }
         }
     };
     ($name:tt, [ $($variant:ident($nested:ty)),* $(,)? ]) => {
-        #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
         pub enum $name {
             $( $variant($nested) ),*
         }
@@ -148,6 +172,43 @@ macro_rules! gen_enum {
                     )*
                 }
             }
+            pub fn variants() -> Vec<Self> {
+                let mut _variants = Vec::new();
+                $(
+                    _variants.extend(<$nested>::variants().into_iter().map(Self::$variant));
+                )*
+                _variants
+            }
+            pub fn path(&self) -> String {
+                match self {
+                    $(
+                        $name::$variant(inner) => format!(
+                            "{}/{}",
+                            stringify!($variant).to_lowercase(),
+                            inner.path()
+                        ),
+                    )*
+                }
+            }
+        }
+        impl ::std::str::FromStr for $name {
+            type Err = $crate::error::Error;
+            /// Parses `variant/nested`, a missing nested part means its default.
+            fn from_str(s: &str) -> ::std::result::Result<Self, Self::Err> {
+                let (head, rest) = s.split_once('/').unwrap_or((s, ""));
+                $(
+                    if head.eq_ignore_ascii_case(stringify!($variant)) {
+                        if rest.is_empty() {
+                            return Ok(Self::$variant(<$nested>::default()));
+                        }
+                        return rest
+                            .parse::<$nested>()
+                            .map(Self::$variant)
+                            .map_err(|_| $crate::error::Error::UnknownCategory(s.to_owned()));
+                    }
+                )*
+                Err($crate::error::Error::UnknownCategory(s.to_owned()))
+            }
         }
     };
 }
@@ -0,0 +1,190 @@
+use std::{borrow::Cow, fmt};
+
+use crate::{agent::ImageUrl, gen_enum};
+
+gen_enum!(MediaKind, [Unknown, Static, Animated, Video]);
+
+impl MediaKind {
+    /// Guesses the media kind from a file extension.
+    ///
+    /// # Example
+    /// ```rust
+    /// use anime_grubber::image::MediaKind;
+    ///
+    /// assert_eq!(MediaKind::from_extension("GIF"), MediaKind::Animated);
+    /// assert_eq!(MediaKind::from_extension("png"), MediaKind::Static);
+    /// assert_eq!(MediaKind::from_extension("exe"), MediaKind::Unknown);
+    /// ```
+    pub fn from_extension(extension: &str) -> Self {
+        match extension.to_lowercase().as_str() {
+            "png" | "jpg" | "jpeg" | "webp" | "bmp" | "avif" => Self::Static,
+            "gif" | "apng" => Self::Animated,
+            "mp4" | "webm" | "mov" | "mkv" => Self::Video,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// Width and height of an image in pixels.
+pub struct Dimensions {
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// An image returned by an [Agent](crate::agent::Agent) together with everything
+/// the provider told us about it.
+///
+/// `kind` and `extension` are derived from the URL, the optional fields are
+/// filled only by providers that expose them. Categories are kept as the
+/// lowercased names the provider uses, so images of every agent look alike.
+///
+/// # Example
+/// ```rust
+/// use anime_grubber::image::{Image, MediaKind};
+/// use anime_grubber::agent::ImageUrl;
+///
+/// let image = Image::new("https://i.waifu.pics/abcd.gif", "waifu_pics", "sfw").with_aspect("dance");
+/// assert_eq!(image.category_path(), "sfw/dance");
+/// assert_eq!(image.kind, MediaKind::Animated);
+/// assert_eq!(image.extension.as_deref(), Some("gif"));
+///
+/// let url: ImageUrl = image.into();
+/// assert_eq!(url, "https://i.waifu.pics/abcd.gif");
+/// ```
+pub struct Image {
+    /// Direct URL of the file.
+    pub url: String,
+    /// Name of the agent which produced the image.
+    pub agent: &'static str,
+    /// Category the image was requested from, e.g. `sfw`.
+    pub category: String,
+    /// Category nested in `category`, e.g. `hug`, for providers which have them.
+    pub aspect: Option<String>,
+    /// Static image, animation or video.
+    pub kind: MediaKind,
+    /// Lowercased file extension without the dot.
+    pub extension: Option<String>,
+    /// Author of the image, if the provider knows it.
+    pub artist: Option<String>,
+    /// Page the image was originally published on.
+    pub source: Option<String>,
+    /// Size of the image in pixels.
+    pub dimensions: Option<Dimensions>,
+}
+
+impl Image {
+    /// Creates a new `Image`, guessing `extension` and `kind` from the URL.
+    pub fn new(url: impl Into<String>, agent: &'static str, category: impl Into<String>) -> Self {
+        let url = url.into();
+        let extension = extension_of(&url);
+        let kind = extension
+            .as_deref()
+            .map(MediaKind::from_extension)
+            .unwrap_or_default();
+        Self {
+            url,
+            agent,
+            category: category.into(),
+            aspect: None,
+            kind,
+            extension,
+            artist: None,
+            source: None,
+            dimensions: None,
+        }
+    }
+
+    /// Sets the category nested in [Image::category].
+    pub fn with_aspect(mut self, aspect: impl Into<String>) -> Self {
+        self.aspect = Some(aspect.into());
+        self
+    }
+
+    /// Sets the artist of the image.
+    pub fn with_artist(mut self, artist: impl Into<String>) -> Self {
+        self.artist = Some(artist.into());
+        self
+    }
+
+    /// Sets the page the image was published on.
+    pub fn with_source(mut self, source: impl Into<String>) -> Self {
+        self.source = Some(source.into());
+        self
+    }
+
+    /// Sets the size of the image.
+    pub fn with_dimensions(mut self, width: u32, height: u32) -> Self {
+        self.dimensions = Some(Dimensions { width, height });
+        self
+    }
+
+    /// Category and aspect joined with `/`, the form agent registries accept.
+    ///
+    /// # Example
+    /// ```rust
+    /// use anime_grubber::image::Image;
+    /// use anime_grubber::agents::waifu_pics::{Categories, SFW};
+    ///
+    /// let image = Image::new("https://i.waifu.pics/abcd.png", "waifu_pics", "sfw").with_aspect("hug");
+    /// assert_eq!(image.category_path(), Categories::SFW(SFW::Hug).path());
+    /// assert_eq!(Image::new("https://example.com/a.png", "test", "art").category_path(), "art");
+    /// ```
+    pub fn category_path(&self) -> String {
+        match &self.aspect {
+            Some(aspect) => format!("{}/{aspect}", self.category),
+            None => self.category.clone(),
+        }
+    }
+
+    /// Returns the last path segment of the URL.
+    ///
+    /// # Example
+    /// ```rust
+    /// use anime_grubber::image::Image;
+    ///
+    /// let image = Image::new("https://i.waifu.pics/abcd.png?x=1", "waifu_pics", "sfw");
+    /// assert_eq!(image.file_name(), "abcd.png");
+    /// ```
+    pub fn file_name(&self) -> &str {
+        file_name_of(&self.url)
+    }
+}
+
+impl fmt::Display for Image {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.url)
+    }
+}
+
+impl AsRef<str> for Image {
+    fn as_ref(&self) -> &str {
+        &self.url
+    }
+}
+
+impl<'a> From<Image> for ImageUrl<'a> {
+    fn from(value: Image) -> Self {
+        Cow::Owned(value.url)
+    }
+}
+
+impl<'a> From<&'a Image> for ImageUrl<'a> {
+    fn from(value: &'a Image) -> Self {
+        Cow::Borrowed(&value.url)
+    }
+}
+
+fn file_name_of(url: &str) -> &str {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    path.rsplit('/').next().unwrap_or_default()
+}
+
+pub(crate) fn extension_of(url: &str) -> Option<String> {
+    file_name_of(url)
+        .rsplit_once('.')
+        .map(|(_, extension)| extension)
+        .filter(|extension| !extension.is_empty())
+        .map(str::to_lowercase)
+}
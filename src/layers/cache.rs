@@ -0,0 +1,337 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    time::Duration,
+};
+
+use crate::{
+    agent::{Agent, Batch, CategorizedAgent, Images},
+    image::Image,
+    result::Result,
+};
+use async_trait::async_trait;
+use tokio::time::Instant;
+use tracing::{debug, instrument, warn};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+/// Counters of a [Cached] agent.
+pub struct CacheStats {
+    /// Requests served from a fresh entry.
+    pub hits: u64,
+    /// Requests served from a stale entry while it was revalidated.
+    pub stale_hits: u64,
+    /// Requests which went to the inner agent.
+    pub misses: u64,
+    /// Entries dropped to stay within the capacity.
+    pub evictions: u64,
+}
+
+#[derive(Debug)]
+struct Entry {
+    images: Images,
+    fetched_at: Instant,
+    used_at: Instant,
+    revalidating: bool,
+}
+
+enum Lookup {
+    Fresh(Images),
+    Stale(Images, bool),
+    Miss,
+}
+
+struct Shared<A: CategorizedAgent> {
+    inner: A,
+    entries: Mutex<HashMap<A::Category, Entry>>,
+    hits: AtomicU64,
+    stale_hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl<A: CategorizedAgent> Shared<A> {
+    fn lock(&self) -> MutexGuard<'_, HashMap<A::Category, Entry>> {
+        self.entries.lock().expect("poisoned cache")
+    }
+
+    fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            stale_hits: self.stale_hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+        }
+    }
+
+    fn lookup(&self, category: A::Category, ttl: Duration, stale: Duration) -> Lookup {
+        let mut entries = self.lock();
+        let Some(entry) = entries.get_mut(&category) else {
+            return Lookup::Miss;
+        };
+        let now = Instant::now();
+        let age = now.duration_since(entry.fetched_at);
+        if age < ttl {
+            entry.used_at = now;
+            Lookup::Fresh(entry.images.clone())
+        } else if age < ttl + stale {
+            entry.used_at = now;
+            let revalidate = !entry.revalidating;
+            entry.revalidating = true;
+            Lookup::Stale(entry.images.clone(), revalidate)
+        } else {
+            Lookup::Miss
+        }
+    }
+
+    async fn fetch(&self, category: A::Category, capacity: usize) -> Result<Images> {
+        let result = self.inner.get_many_in(category).await;
+        let mut entries = self.lock();
+        let images = match result {
+            Ok(images) => images,
+            Err(error) => {
+                if let Some(entry) = entries.get_mut(&category) {
+                    entry.revalidating = false;
+                }
+                return Err(error);
+            }
+        };
+        let now = Instant::now();
+        entries.insert(
+            category,
+            Entry {
+                images: images.clone(),
+                fetched_at: now,
+                used_at: now,
+                revalidating: false,
+            },
+        );
+        while entries.len() > capacity {
+            let Some(oldest) = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.used_at)
+                .map(|(category, _)| *category)
+            else {
+                break;
+            };
+            debug!("Evict {oldest:?}");
+            entries.remove(&oldest);
+            self.evictions.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(images)
+    }
+}
+
+/// An [Agent] caching `get_many` results per category.
+///
+/// Entries live for `ttl`. During the following `stale_while_revalidate`
+/// window the old entry is still returned while a fresh one is fetched in the
+/// background. At most `capacity` categories are kept, the least recently
+/// used one is dropped first. Hits and misses are reported as `debug` events
+/// with running counters, see also [Cached::stats]. `get`, `get_random` and
+/// `get_batch` are not cached, a batch would otherwise repeat the cached
+/// images until the TTL runs out. Clones share the cache.
+///
+/// Background revalidation uses `tokio::spawn`, so the agent must be used
+/// inside a Tokio runtime.
+///
+/// # Example
+/// ```rust
+/// use std::time::Duration;
+/// use anime_grubber::layers::cache::Cached;
+/// use anime_grubber::agents::waifu_pics::{Waifu, Categories};
+///
+/// let waifu = Cached::new(Waifu::new(Categories::default()))
+///     .ttl(Duration::from_secs(60))
+///     .stale_while_revalidate(Duration::from_secs(30))
+///     .capacity(16);
+/// ```
+pub struct Cached<A: CategorizedAgent> {
+    shared: Arc<Shared<A>>,
+    category: A::Category,
+    ttl: Duration,
+    stale: Duration,
+    capacity: usize,
+}
+
+impl<A: CategorizedAgent> Clone for Cached<A> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+            category: self.category,
+            ttl: self.ttl,
+            stale: self.stale,
+            capacity: self.capacity,
+        }
+    }
+}
+
+impl<A: CategorizedAgent + fmt::Debug> fmt::Debug for Cached<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cached")
+            .field("inner", &self.shared.inner)
+            .field("category", &self.category)
+            .field("ttl", &self.ttl)
+            .field("stale_while_revalidate", &self.stale)
+            .field("capacity", &self.capacity)
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+impl<A: CategorizedAgent> Cached<A> {
+    /// Returns the wrapped agent.
+    pub fn inner(&self) -> &A {
+        &self.shared.inner
+    }
+
+    /// Returns the counters of the cache.
+    pub fn stats(&self) -> CacheStats {
+        self.shared.stats()
+    }
+
+    /// Drops every entry.
+    pub fn clear(&self) {
+        self.shared.lock().clear();
+    }
+}
+
+impl<A> Cached<A>
+where
+    A: CategorizedAgent + Send + Sync + 'static,
+{
+    /// Wraps `inner` with a 5 minute TTL, no stale window and room for 64 categories.
+    pub fn new(inner: A) -> Self {
+        Self {
+            category: inner.category(),
+            shared: Arc::new(Shared {
+                inner,
+                entries: Mutex::default(),
+                hits: AtomicU64::new(0),
+                stale_hits: AtomicU64::new(0),
+                misses: AtomicU64::new(0),
+                evictions: AtomicU64::new(0),
+            }),
+            ttl: Duration::from_secs(300),
+            stale: Duration::ZERO,
+            capacity: 64,
+        }
+    }
+
+    /// Sets how long an entry is fresh.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Sets how long an expired entry may still be served while it is refreshed.
+    pub fn stale_while_revalidate(mut self, stale: Duration) -> Self {
+        self.stale = stale;
+        self
+    }
+
+    /// Sets how many categories are kept at most.
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity.max(1);
+        self
+    }
+
+    async fn cached(&self, category: A::Category) -> Result<Images> {
+        match self.shared.lookup(category, self.ttl, self.stale) {
+            Lookup::Fresh(images) => {
+                let hits = self.shared.hits.fetch_add(1, Ordering::Relaxed) + 1;
+                debug!(hits, "Cache hit for {category:?}");
+                Ok(images)
+            }
+            Lookup::Stale(images, revalidate) => {
+                let stale_hits = self.shared.stale_hits.fetch_add(1, Ordering::Relaxed) + 1;
+                debug!(stale_hits, "Stale cache hit for {category:?}");
+                if revalidate {
+                    let shared = self.shared.clone();
+                    let capacity = self.capacity;
+                    tokio::spawn(async move {
+                        if let Err(error) = shared.fetch(category, capacity).await {
+                            warn!("Revalidation of {category:?} failed: {error}");
+                        }
+                    });
+                }
+                Ok(images)
+            }
+            Lookup::Miss => {
+                let misses = self.shared.misses.fetch_add(1, Ordering::Relaxed) + 1;
+                debug!(misses, "Cache miss for {category:?}");
+                self.shared.fetch(category, self.capacity).await
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<A> Agent for Cached<A>
+where
+    A: CategorizedAgent + Send + Sync + 'static,
+{
+    #[instrument(skip(self))]
+    async fn get(&self) -> Result<Image> {
+        self.shared.inner.get_in(self.category).await
+    }
+
+    #[instrument(skip(self))]
+    async fn get_many(&self) -> Result<Images> {
+        self.cached(self.category).await
+    }
+
+    #[instrument(skip(self))]
+    async fn get_random(&self) -> Result<Image> {
+        self.shared.inner.get_random().await
+    }
+
+    #[instrument(skip(self))]
+    async fn get_batch(&self, count: usize) -> Result<Images> {
+        if self.shared.inner.category() == self.category {
+            return self.shared.inner.get_batch(count).await;
+        }
+        let mut batch = Batch::new(count);
+        while !batch.is_done() {
+            batch.extend(self.shared.inner.get_many_in(self.category).await?);
+        }
+        Ok(batch.finish())
+    }
+
+    fn max_batch(&self) -> usize {
+        self.shared.inner.max_batch()
+    }
+}
+
+#[async_trait]
+impl<A> CategorizedAgent for Cached<A>
+where
+    A: CategorizedAgent + Send + Sync + 'static,
+{
+    type Category = A::Category;
+
+    fn categories() -> Vec<Self::Category> {
+        A::categories()
+    }
+
+    fn category(&self) -> Self::Category {
+        self.category
+    }
+
+    fn set_category(&mut self, category: Self::Category) {
+        self.category = category;
+    }
+
+    #[instrument(skip(self))]
+    async fn get_in(&self, category: Self::Category) -> Result<Image> {
+        self.shared.inner.get_in(category).await
+    }
+
+    #[instrument(skip(self))]
+    async fn get_many_in(&self, category: Self::Category) -> Result<Images> {
+        self.cached(category).await
+    }
+}
@@ -0,0 +1,334 @@
+use std::{
+    fmt,
+    future::Future,
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
+use crate::{
+    agent::{Agent, CategorizedAgent, Images},
+    error::Error,
+    image::Image,
+    layers::retry::RetryPolicy,
+    result::Result,
+};
+use async_trait::async_trait;
+use tokio::time::Instant;
+use tracing::{debug, info, instrument, warn};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+/// State of a [CircuitBreaker].
+pub enum Health {
+    /// Requests go through.
+    #[default]
+    Closed,
+    /// Requests are refused.
+    Open,
+    /// A few probe requests decide whether to close again.
+    HalfOpen,
+}
+
+impl From<&Health> for &str {
+    fn from(value: &Health) -> Self {
+        match value {
+            Health::Closed => "Closed",
+            Health::Open => "Open",
+            Health::HalfOpen => "HalfOpen",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+/// Snapshot of the breaker for dashboards.
+pub struct HealthReport {
+    /// Current state of the breaker.
+    pub health: Health,
+    /// Failures since the last success.
+    pub consecutive_failures: u32,
+    /// Successful requests since creation.
+    pub successes: u64,
+    /// Failed requests since creation.
+    pub failures: u64,
+    /// Requests refused without calling the inner agent.
+    pub rejected: u64,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    report: HealthReport,
+    opened_at: Option<Instant>,
+    probes: u32,
+}
+
+type Classifier = Arc<dyn Fn(&Error) -> bool + Send + Sync>;
+
+#[derive(Clone)]
+/// An [Agent] which stops calling the inner agent after repeated failures.
+///
+/// After `failure_threshold` failures in a row the breaker opens and every
+/// request fails at once with [Error::CircuitOpen]. Once `open_for` has passed,
+/// up to `half_open_probes` requests are let through: a success closes the
+/// breaker, a failure opens it again. Clones share the state.
+///
+/// Only errors accepted by [RetryPolicy::is_transient] count as failures by default.
+///
+/// # Example
+/// ```rust
+/// use std::time::Duration;
+/// use anime_grubber::layers::circuit_breaker::{CircuitBreaker, Health};
+/// use anime_grubber::agents::waifu_pics::{Waifu, Categories};
+///
+/// let waifu = CircuitBreaker::new(Waifu::new(Categories::default()))
+///     .failure_threshold(3)
+///     .open_for(Duration::from_secs(10));
+/// assert_eq!(waifu.health(), Health::Closed);
+/// ```
+pub struct CircuitBreaker<A> {
+    inner: A,
+    failure_threshold: u32,
+    open_for: Duration,
+    half_open_probes: u32,
+    is_failure: Classifier,
+    state: Arc<Mutex<State>>,
+}
+
+impl<A: fmt::Debug> fmt::Debug for CircuitBreaker<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CircuitBreaker")
+            .field("inner", &self.inner)
+            .field("failure_threshold", &self.failure_threshold)
+            .field("open_for", &self.open_for)
+            .field("half_open_probes", &self.half_open_probes)
+            .field("report", &self.report())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<A> CircuitBreaker<A> {
+    /// Wraps `inner`, opening after 5 failures for 30 seconds with one probe.
+    pub fn new(inner: A) -> Self {
+        Self {
+            inner,
+            failure_threshold: 5,
+            open_for: Duration::from_secs(30),
+            half_open_probes: 1,
+            is_failure: Arc::new(RetryPolicy::is_transient),
+            state: Arc::default(),
+        }
+    }
+
+    /// Sets how many failures in a row open the breaker.
+    pub fn failure_threshold(mut self, threshold: u32) -> Self {
+        self.failure_threshold = threshold.max(1);
+        self
+    }
+
+    /// Sets how long the breaker stays open before probing.
+    pub fn open_for(mut self, open_for: Duration) -> Self {
+        self.open_for = open_for;
+        self
+    }
+
+    /// Sets how many requests may probe a half-open breaker at once.
+    pub fn half_open_probes(mut self, probes: u32) -> Self {
+        self.half_open_probes = probes.max(1);
+        self
+    }
+
+    /// Sets which errors count as failures.
+    pub fn failure_on(
+        mut self,
+        is_failure: impl Fn(&Error) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.is_failure = Arc::new(is_failure);
+        self
+    }
+
+    /// Returns the current state of the breaker.
+    pub fn health(&self) -> Health {
+        self.report().health
+    }
+
+    /// Returns the state of the breaker together with its counters.
+    pub fn report(&self) -> HealthReport {
+        let mut state = self.lock();
+        self.refresh(&mut state);
+        state.report
+    }
+
+    /// Returns the wrapped agent.
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    /// Unwraps the inner agent.
+    pub fn into_inner(self) -> A {
+        self.inner
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().expect("poisoned circuit breaker")
+    }
+
+    /// Moves an open breaker to half-open once `open_for` has passed.
+    fn refresh(&self, state: &mut State) {
+        if let (Health::Open, Some(opened_at)) = (state.report.health, state.opened_at) {
+            if opened_at.elapsed() >= self.open_for {
+                info!("Circuit breaker is half-open");
+                state.report.health = Health::HalfOpen;
+                state.probes = 0;
+            }
+        }
+    }
+
+    /// Lets a request through, telling whether it is a probe.
+    fn admit(&self) -> Result<bool> {
+        let mut state = self.lock();
+        self.refresh(&mut state);
+        match state.report.health {
+            Health::Closed => Ok(false),
+            Health::HalfOpen if state.probes < self.half_open_probes => {
+                state.probes += 1;
+                Ok(true)
+            }
+            Health::Open | Health::HalfOpen => {
+                state.report.rejected += 1;
+                let retry_after = state
+                    .opened_at
+                    .map(|opened_at| self.open_for.saturating_sub(opened_at.elapsed()))
+                    .unwrap_or_default();
+                Err(Error::CircuitOpen { retry_after })
+            }
+        }
+    }
+
+    fn record(&self, probe: bool, failed: bool) {
+        let mut state = self.lock();
+        if probe {
+            state.probes = state.probes.saturating_sub(1);
+        }
+        let report = &mut state.report;
+        if !failed {
+            report.successes += 1;
+            match report.health {
+                Health::Closed => report.consecutive_failures = 0,
+                Health::HalfOpen if probe => {
+                    info!("Circuit breaker is closed");
+                    report.health = Health::Closed;
+                    report.consecutive_failures = 0;
+                }
+                // Admitted before the breaker opened, says nothing about now.
+                Health::HalfOpen | Health::Open => debug!("Ignore late success"),
+            }
+            return;
+        }
+        report.failures += 1;
+        report.consecutive_failures += 1;
+        let trip = match report.health {
+            Health::Closed => report.consecutive_failures >= self.failure_threshold,
+            Health::HalfOpen if probe => true,
+            // Admitted before the breaker opened, like a late success.
+            Health::HalfOpen | Health::Open => false,
+        };
+        if trip {
+            warn!(
+                "Circuit breaker is open after {} failures",
+                report.consecutive_failures
+            );
+            report.health = Health::Open;
+            state.opened_at = Some(Instant::now());
+        }
+    }
+
+    /// Runs `request` if the breaker lets it through and records the outcome.
+    ///
+    /// # Errors
+    /// Returns [Error::CircuitOpen] if the breaker is open, otherwise the error of `request`.
+    pub async fn call<T>(&self, request: impl Future<Output = Result<T>>) -> Result<T> {
+        let probe = self.admit()?;
+        let mut guard = ProbeGuard {
+            breaker: self,
+            probe,
+        };
+        let result = request.await;
+        guard.probe = false;
+        let failed = matches!(&result, Err(error) if (self.is_failure)(error));
+        self.record(probe, failed);
+        result
+    }
+}
+
+/// Frees the probe slot if the request is cancelled.
+struct ProbeGuard<'a, A> {
+    breaker: &'a CircuitBreaker<A>,
+    probe: bool,
+}
+
+impl<A> Drop for ProbeGuard<'_, A> {
+    fn drop(&mut self) {
+        if self.probe {
+            let mut state = self.breaker.lock();
+            state.probes = state.probes.saturating_sub(1);
+        }
+    }
+}
+
+#[async_trait]
+impl<A> Agent for CircuitBreaker<A>
+where
+    A: Agent + Send + Sync,
+{
+    #[instrument(skip(self))]
+    async fn get(&self) -> Result<Image> {
+        self.call(self.inner.get()).await
+    }
+
+    #[instrument(skip(self))]
+    async fn get_many(&self) -> Result<Images> {
+        self.call(self.inner.get_many()).await
+    }
+
+    #[instrument(skip(self))]
+    async fn get_random(&self) -> Result<Image> {
+        self.call(self.inner.get_random()).await
+    }
+
+    #[instrument(skip(self))]
+    async fn get_batch(&self, count: usize) -> Result<Images> {
+        self.call(self.inner.get_batch(count)).await
+    }
+
+    fn max_batch(&self) -> usize {
+        self.inner.max_batch()
+    }
+}
+
+#[async_trait]
+impl<A> CategorizedAgent for CircuitBreaker<A>
+where
+    A: CategorizedAgent + Send + Sync,
+{
+    type Category = A::Category;
+
+    fn categories() -> Vec<Self::Category> {
+        A::categories()
+    }
+
+    fn category(&self) -> Self::Category {
+        self.inner.category()
+    }
+
+    fn set_category(&mut self, category: Self::Category) {
+        self.inner.set_category(category);
+    }
+
+    #[instrument(skip(self))]
+    async fn get_in(&self, category: Self::Category) -> Result<Image> {
+        self.call(self.inner.get_in(category)).await
+    }
+
+    #[instrument(skip(self))]
+    async fn get_many_in(&self, category: Self::Category) -> Result<Images> {
+        self.call(self.inner.get_many_in(category)).await
+    }
+}
@@ -0,0 +1,178 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    future::Future,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    agent::{Agent, CategorizedAgent, Images},
+    error::Error,
+    image::Image,
+    result::Result,
+};
+use async_trait::async_trait;
+use tokio::sync::OnceCell;
+use tracing::{debug, instrument};
+
+type Flight = Arc<OnceCell<std::result::Result<Images, Arc<Error>>>>;
+
+/// An [Agent] sharing one in-flight `get_many` between concurrent callers.
+///
+/// While a `get_many` for a category is running, further calls for the same
+/// category wait for it instead of sending their own request, and every caller
+/// receives the same images. `get_many` shares requests with `get_many_in` of
+/// the agent's own category. Once the request finishes, the next call starts
+/// a new one, so results are never reused afterwards.
+///
+/// A caller whose request was not shared receives the original error. Errors
+/// of shared requests are handed out as [Error::Shared], so match on
+/// [Error::root] to see through them.
+///
+/// `get` and `get_random` are expected to return different images on every
+/// call and are passed through, as is `get_batch`, so that it keeps the
+/// batching of the inner agent. Clones share in-flight requests.
+///
+/// # Example
+/// ```rust
+/// use anime_grubber::layers::coalesce::Coalesced;
+/// use anime_grubber::agents::waifu_pics::{Waifu, Categories};
+///
+/// let waifu = Coalesced::new(Waifu::new(Categories::default()));
+/// ```
+#[derive(Clone)]
+pub struct Coalesced<A: CategorizedAgent> {
+    inner: A,
+    flights: Arc<Mutex<HashMap<A::Category, Flight>>>,
+}
+
+impl<A: CategorizedAgent + fmt::Debug> fmt::Debug for Coalesced<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Coalesced")
+            .field("inner", &self.inner)
+            .field("in_flight", &self.in_flight())
+            .finish()
+    }
+}
+
+impl<A: CategorizedAgent> Coalesced<A> {
+    /// Wraps `inner`.
+    pub fn new(inner: A) -> Self {
+        Self {
+            inner,
+            flights: Arc::default(),
+        }
+    }
+
+    /// Returns the wrapped agent.
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    /// Unwraps the inner agent.
+    pub fn into_inner(self) -> A {
+        self.inner
+    }
+
+    /// Number of requests currently shared between callers.
+    pub fn in_flight(&self) -> usize {
+        self.flights.lock().expect("poisoned flights").len()
+    }
+
+    async fn coalesce<F, Fut>(&self, key: A::Category, request: F) -> Result<Images>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Images>>,
+    {
+        let flight = {
+            let mut flights = self.flights.lock().expect("poisoned flights");
+            match flights.get(&key) {
+                Some(flight) => {
+                    debug!("Join in-flight request for {key:?}");
+                    flight.clone()
+                }
+                None => flights.entry(key).or_default().clone(),
+            }
+        };
+        flight
+            .get_or_init(|| async { request().await.map_err(Arc::new) })
+            .await;
+        {
+            let mut flights = self.flights.lock().expect("poisoned flights");
+            if flights
+                .get(&key)
+                .is_some_and(|current| Arc::ptr_eq(current, &flight))
+            {
+                flights.remove(&key);
+            }
+        }
+        // The last holder of an unshared flight takes its result back out.
+        let result = match Arc::try_unwrap(flight) {
+            Ok(flight) => flight.into_inner().expect("flight finished"),
+            Err(flight) => flight.get().expect("flight finished").clone(),
+        };
+        result.map_err(|error| Arc::try_unwrap(error).unwrap_or_else(Error::Shared))
+    }
+}
+
+#[async_trait]
+impl<A> Agent for Coalesced<A>
+where
+    A: CategorizedAgent + Send + Sync,
+{
+    #[instrument(skip(self))]
+    async fn get(&self) -> Result<Image> {
+        self.inner.get().await
+    }
+
+    #[instrument(skip(self))]
+    async fn get_many(&self) -> Result<Images> {
+        self.coalesce(self.inner.category(), || self.inner.get_many())
+            .await
+    }
+
+    #[instrument(skip(self))]
+    async fn get_random(&self) -> Result<Image> {
+        self.inner.get_random().await
+    }
+
+    #[instrument(skip(self))]
+    async fn get_batch(&self, count: usize) -> Result<Images> {
+        self.inner.get_batch(count).await
+    }
+
+    fn max_batch(&self) -> usize {
+        self.inner.max_batch()
+    }
+}
+
+#[async_trait]
+impl<A> CategorizedAgent for Coalesced<A>
+where
+    A: CategorizedAgent + Send + Sync,
+{
+    type Category = A::Category;
+
+    fn categories() -> Vec<Self::Category> {
+        A::categories()
+    }
+
+    fn category(&self) -> Self::Category {
+        self.inner.category()
+    }
+
+    fn set_category(&mut self, category: Self::Category) {
+        self.inner.set_category(category);
+    }
+
+    #[instrument(skip(self))]
+    async fn get_in(&self, category: Self::Category) -> Result<Image> {
+        self.inner.get_in(category).await
+    }
+
+    #[instrument(skip(self))]
+    async fn get_many_in(&self, category: Self::Category) -> Result<Images> {
+        self.coalesce(category, || self.inner.get_many_in(category))
+            .await
+    }
+}
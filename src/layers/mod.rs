@@ -0,0 +1,14 @@
+//! Wrappers adding behaviour to any [Agent](crate::agent::Agent)
+
+/// Caching `get_many` results
+pub mod cache;
+/// Failing fast while an agent is unhealthy
+pub mod circuit_breaker;
+/// Sharing concurrent identical requests
+pub mod coalesce;
+/// Serving single images from prefetched batches
+pub mod prefetch;
+/// Client-side request rate limiting
+pub mod rate_limit;
+/// Retrying failed requests
+pub mod retry;
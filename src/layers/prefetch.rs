@@ -0,0 +1,271 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use crate::{
+    agent::{Agent, Batch, CategorizedAgent, Images},
+    error::Error,
+    image::Image,
+    result::Result,
+};
+use async_trait::async_trait;
+use tracing::{debug, instrument, warn};
+
+#[derive(Debug, Default)]
+struct Buffer {
+    images: VecDeque<Image>,
+    /// Held while the buffer is being refilled.
+    refill: Arc<tokio::sync::Mutex<()>>,
+    /// Whether a background refill is scheduled.
+    refilling: bool,
+}
+
+struct Shared<A: CategorizedAgent> {
+    inner: A,
+    buffers: Mutex<HashMap<A::Category, Buffer>>,
+}
+
+impl<A: CategorizedAgent> Shared<A> {
+    fn lock(&self) -> MutexGuard<'_, HashMap<A::Category, Buffer>> {
+        self.buffers.lock().expect("poisoned prefetch buffer")
+    }
+
+    fn len(&self, category: A::Category) -> usize {
+        self.lock()
+            .get(&category)
+            .map_or(0, |buffer| buffer.images.len())
+    }
+
+    /// Takes an image, telling whether a background refill should start.
+    fn pop(&self, category: A::Category, low_water: usize) -> (Option<Image>, bool) {
+        let mut buffers = self.lock();
+        let buffer = buffers.entry(category).or_default();
+        let image = buffer.images.pop_front();
+        let refill = image.is_some() && buffer.images.len() < low_water && !buffer.refilling;
+        if refill {
+            buffer.refilling = true;
+        }
+        (image, refill)
+    }
+
+    /// Fetches a batch unless the buffer already holds `threshold` images.
+    ///
+    /// Returns the amount of buffered images.
+    async fn refill(&self, category: A::Category, threshold: usize) -> Result<usize> {
+        let lock = self.lock().entry(category).or_default().refill.clone();
+        let _guard = lock.lock().await;
+        let len = self.len(category);
+        if len >= threshold {
+            return Ok(len);
+        }
+
+        debug!("Refill {category:?} with {len} images left");
+        let images = self.inner.get_many_in(category).await?;
+        let mut buffers = self.lock();
+        let buffer = buffers.entry(category).or_default();
+        let mut seen: HashSet<String> = buffer
+            .images
+            .iter()
+            .map(|image| image.url.clone())
+            .collect();
+        buffer.images.extend(
+            images
+                .into_vec()
+                .into_iter()
+                .filter(|image| seen.insert(image.url.clone())),
+        );
+        Ok(buffer.images.len())
+    }
+}
+
+/// An [Agent] serving `get` from batches fetched with `get_many`.
+///
+/// Every category has its own buffer. Taking an image is O(1); once a buffer
+/// holds fewer than `low_water` images, a new batch is fetched in the
+/// background. Images of one batch are de-duplicated, so the same URL is
+/// never handed out twice from it. `get_many`, `get_random` and `get_batch`
+/// go straight to the inner agent, the latter using its own batching when
+/// the categories match. Clones share the buffers.
+///
+/// Background refills are spawned with `tokio::spawn`, so the agent must be
+/// used inside a Tokio runtime.
+///
+/// # Example
+/// ```rust
+/// use anime_grubber::agent::Agent;
+/// use anime_grubber::layers::prefetch::Prefetched;
+/// use anime_grubber::agents::waifu_pics::{Waifu, Categories};
+///
+/// async fn example() -> anime_grubber::Result<()> {
+///     let waifu = Prefetched::new(Waifu::new(Categories::default())).low_water(10);
+///     // first call fetches 30 images, the next 29 are served from memory
+///     let image = waifu.get().await?;
+///     Ok(())
+/// }
+/// ```
+pub struct Prefetched<A: CategorizedAgent> {
+    shared: Arc<Shared<A>>,
+    category: A::Category,
+    low_water: usize,
+}
+
+impl<A: CategorizedAgent> Clone for Prefetched<A> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+            category: self.category,
+            low_water: self.low_water,
+        }
+    }
+}
+
+impl<A: CategorizedAgent + fmt::Debug> fmt::Debug for Prefetched<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Prefetched")
+            .field("inner", &self.shared.inner)
+            .field("category", &self.category)
+            .field("low_water", &self.low_water)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<A> Prefetched<A>
+where
+    A: CategorizedAgent + Send + Sync + 'static,
+{
+    /// Wraps `inner`, refilling a buffer when it drops below a third of [Agent::max_batch].
+    pub fn new(inner: A) -> Self {
+        let category = inner.category();
+        let low_water = (inner.max_batch() / 3).max(1);
+        Self {
+            shared: Arc::new(Shared {
+                inner,
+                buffers: Mutex::default(),
+            }),
+            category,
+            low_water,
+        }
+    }
+
+    /// Sets the amount of buffered images below which a refill starts.
+    pub fn low_water(mut self, low_water: usize) -> Self {
+        self.low_water = low_water;
+        self
+    }
+
+    /// Returns the wrapped agent.
+    pub fn inner(&self) -> &A {
+        &self.shared.inner
+    }
+
+    /// Returns the amount of images buffered for `category`.
+    pub fn buffered(&self, category: A::Category) -> usize {
+        self.shared.len(category)
+    }
+
+    /// Fills the buffer of `category` ahead of time.
+    ///
+    /// # Errors
+    /// Returns error if the batch cannot be retrieved.
+    pub async fn prefetch(&self, category: A::Category) -> Result<()> {
+        self.shared.refill(category, self.low_water.max(1)).await?;
+        Ok(())
+    }
+
+    fn spawn_refill(&self, category: A::Category) {
+        let shared = self.shared.clone();
+        let low_water = self.low_water;
+        tokio::spawn(async move {
+            if let Err(error) = shared.refill(category, low_water).await {
+                warn!("Background refill of {category:?} failed: {error}");
+            }
+            if let Some(buffer) = shared.lock().get_mut(&category) {
+                buffer.refilling = false;
+            }
+        });
+    }
+
+    async fn take(&self, category: A::Category) -> Result<Image> {
+        loop {
+            let (image, refill) = self.shared.pop(category, self.low_water);
+            if refill {
+                self.spawn_refill(category);
+            }
+            if let Some(image) = image {
+                return Ok(image);
+            }
+            if self.shared.refill(category, 1).await? == 0 {
+                return Err(Error::NotFound);
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<A> Agent for Prefetched<A>
+where
+    A: CategorizedAgent + Send + Sync + 'static,
+{
+    #[instrument(skip(self))]
+    async fn get(&self) -> Result<Image> {
+        self.take(self.category).await
+    }
+
+    #[instrument(skip(self))]
+    async fn get_many(&self) -> Result<Images> {
+        self.shared.inner.get_many_in(self.category).await
+    }
+
+    #[instrument(skip(self))]
+    async fn get_random(&self) -> Result<Image> {
+        self.shared.inner.get_random().await
+    }
+
+    #[instrument(skip(self))]
+    async fn get_batch(&self, count: usize) -> Result<Images> {
+        if self.shared.inner.category() == self.category {
+            return self.shared.inner.get_batch(count).await;
+        }
+        let mut batch = Batch::new(count);
+        while !batch.is_done() {
+            batch.extend(self.shared.inner.get_many_in(self.category).await?);
+        }
+        Ok(batch.finish())
+    }
+
+    fn max_batch(&self) -> usize {
+        self.shared.inner.max_batch()
+    }
+}
+
+#[async_trait]
+impl<A> CategorizedAgent for Prefetched<A>
+where
+    A: CategorizedAgent + Send + Sync + 'static,
+{
+    type Category = A::Category;
+
+    fn categories() -> Vec<Self::Category> {
+        A::categories()
+    }
+
+    fn category(&self) -> Self::Category {
+        self.category
+    }
+
+    fn set_category(&mut self, category: Self::Category) {
+        self.category = category;
+    }
+
+    #[instrument(skip(self))]
+    async fn get_in(&self, category: Self::Category) -> Result<Image> {
+        self.take(category).await
+    }
+
+    #[instrument(skip(self))]
+    async fn get_many_in(&self, category: Self::Category) -> Result<Images> {
+        self.shared.inner.get_many_in(category).await
+    }
+}
@@ -0,0 +1,268 @@
+use std::{
+    fmt::Debug,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::{
+    agent::{Agent, CategorizedAgent, Images},
+    error::Error,
+    image::Image,
+    middleware::Middleware,
+    result::Result,
+    transport::{Request, Response},
+};
+use async_trait::async_trait;
+use tokio::time::Instant;
+use tracing::{debug, instrument};
+
+#[async_trait]
+/// Decides when the next request may be sent.
+///
+/// Implement it to plug a custom limiter into [RateLimited].
+pub trait RateLimiter: Debug + Send + Sync {
+    /// Waits until a request may be sent.
+    ///
+    /// # Errors
+    /// Returns [Error::RateLimit] if the limiter refuses to wait.
+    async fn acquire(&self) -> Result<()>;
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+#[derive(Debug)]
+/// Token bucket allowing `burst` requests at once and `rate` requests per second on average.
+///
+/// When the bucket is empty, [RateLimiter::acquire] waits for the next token,
+/// or fails with [Error::RateLimit] if the bucket was made [TokenBucket::fail_fast].
+///
+/// # Example
+/// ```rust
+/// use anime_grubber::layers::rate_limit::{RateLimited, TokenBucket};
+/// use anime_grubber::agents::waifu_pics::{Waifu, Categories};
+///
+/// // 2 requests per second, up to 5 at once
+/// let waifu = RateLimited::new(Waifu::new(Categories::default()), TokenBucket::new(2.0, 5)?);
+/// // clones share the same bucket
+/// let clone = waifu.clone();
+/// # Ok::<(), anime_grubber::Error>(())
+/// ```
+pub struct TokenBucket {
+    rate: f64,
+    burst: f64,
+    wait: bool,
+    bucket: Mutex<Bucket>,
+}
+
+impl TokenBucket {
+    /// Creates a full bucket.
+    ///
+    /// # Parameters
+    /// - `rate`: Requests per second, must be positive.
+    /// - `burst`: Requests which may be sent at once, at least 1.
+    ///
+    /// # Errors
+    /// Returns [Error::InvalidConfig] if `rate` is not a positive finite number.
+    pub fn new(rate: f64, burst: u32) -> Result<Self> {
+        if !rate.is_finite() || rate <= 0.0 {
+            return Err(Error::InvalidConfig(format!(
+                "rate must be positive, got {rate}"
+            )));
+        }
+        let burst = f64::from(burst.max(1));
+        Ok(Self {
+            rate,
+            burst,
+            wait: true,
+            bucket: Mutex::new(Bucket {
+                tokens: burst,
+                updated: Instant::now(),
+            }),
+        })
+    }
+
+    /// Makes the bucket fail with [Error::RateLimit] instead of waiting.
+    pub fn fail_fast(mut self) -> Self {
+        self.wait = false;
+        self
+    }
+
+    /// Takes a token, returning how long to wait until it is available.
+    fn reserve(&self) -> Result<Duration> {
+        let mut bucket = self.bucket.lock().expect("poisoned token bucket");
+        let now = Instant::now();
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.burst);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(Duration::ZERO);
+        }
+        let delay = Duration::from_secs_f64((1.0 - bucket.tokens) / self.rate);
+        if !self.wait {
+            return Err(Error::RateLimit {
+                retry_after: Some(delay),
+            });
+        }
+        bucket.tokens -= 1.0;
+        Ok(delay)
+    }
+}
+
+#[async_trait]
+impl RateLimiter for TokenBucket {
+    async fn acquire(&self) -> Result<()> {
+        let delay = self.reserve()?;
+        if !delay.is_zero() {
+            debug!("Rate limited, wait {delay:?}");
+            tokio::time::sleep(delay).await;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+/// An [Agent] asking a [RateLimiter] before every request of the inner agent.
+///
+/// Clones share the limiter, and so do all categories of a [CategorizedAgent].
+/// `get_batch` collects through the limited `get_many`, so it cannot use the
+/// inner agent's own batching, e.g. the exclude list of
+/// [Waifu::get_batch](crate::agents::waifu_pics::Waifu). To keep it, limit
+/// the agent's requests with [RateLimitMiddleware] instead.
+pub struct RateLimited<A> {
+    inner: A,
+    limiter: Arc<dyn RateLimiter>,
+}
+
+impl<A> RateLimited<A> {
+    /// Wraps `inner` with its own `limiter`.
+    pub fn new(inner: A, limiter: impl RateLimiter + 'static) -> Self {
+        Self::with_shared(inner, Arc::new(limiter))
+    }
+
+    /// Wraps `inner` with a limiter shared with other agents.
+    pub fn with_shared(inner: A, limiter: Arc<dyn RateLimiter>) -> Self {
+        Self { inner, limiter }
+    }
+
+    /// Returns the wrapped agent.
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    /// Returns the limiter of the wrapper.
+    pub fn limiter(&self) -> &Arc<dyn RateLimiter> {
+        &self.limiter
+    }
+
+    /// Unwraps the inner agent.
+    pub fn into_inner(self) -> A {
+        self.inner
+    }
+}
+
+#[async_trait]
+impl<A> Agent for RateLimited<A>
+where
+    A: Agent + Send + Sync,
+{
+    #[instrument(skip(self))]
+    async fn get(&self) -> Result<Image> {
+        self.limiter.acquire().await?;
+        self.inner.get().await
+    }
+
+    #[instrument(skip(self))]
+    async fn get_many(&self) -> Result<Images> {
+        self.limiter.acquire().await?;
+        self.inner.get_many().await
+    }
+
+    #[instrument(skip(self))]
+    async fn get_random(&self) -> Result<Image> {
+        self.limiter.acquire().await?;
+        self.inner.get_random().await
+    }
+
+    fn max_batch(&self) -> usize {
+        self.inner.max_batch()
+    }
+}
+
+#[async_trait]
+impl<A> CategorizedAgent for RateLimited<A>
+where
+    A: CategorizedAgent + Send + Sync,
+{
+    type Category = A::Category;
+
+    fn categories() -> Vec<Self::Category> {
+        A::categories()
+    }
+
+    fn category(&self) -> Self::Category {
+        self.inner.category()
+    }
+
+    fn set_category(&mut self, category: Self::Category) {
+        self.inner.set_category(category);
+    }
+
+    #[instrument(skip(self))]
+    async fn get_in(&self, category: Self::Category) -> Result<Image> {
+        self.limiter.acquire().await?;
+        self.inner.get_in(category).await
+    }
+
+    #[instrument(skip(self))]
+    async fn get_many_in(&self, category: Self::Category) -> Result<Images> {
+        self.limiter.acquire().await?;
+        self.inner.get_many_in(category).await
+    }
+}
+
+#[derive(Debug, Clone)]
+/// [Middleware] asking a [RateLimiter] before every request of an
+/// [AgentContext](crate::context::AgentContext).
+///
+/// Unlike [RateLimited] it sees every request an agent sends, also the
+/// rounds of its `get_batch` and downloads through the same context.
+///
+/// # Example
+/// ```rust
+/// use anime_grubber::layers::rate_limit::{RateLimitMiddleware, TokenBucket};
+/// use anime_grubber::agents::waifu_pics::Waifu;
+///
+/// let waifu = Waifu::builder()
+///     .middleware(RateLimitMiddleware::new(TokenBucket::new(2.0, 5)?))
+///     .build()?;
+/// # Ok::<(), anime_grubber::Error>(())
+/// ```
+pub struct RateLimitMiddleware {
+    limiter: Arc<dyn RateLimiter>,
+}
+
+impl RateLimitMiddleware {
+    /// Limits requests with its own `limiter`.
+    pub fn new(limiter: impl RateLimiter + 'static) -> Self {
+        Self::with_shared(Arc::new(limiter))
+    }
+
+    /// Limits requests with a limiter shared with agents or other contexts.
+    pub fn with_shared(limiter: Arc<dyn RateLimiter>) -> Self {
+        Self { limiter }
+    }
+}
+
+#[async_trait]
+impl Middleware for RateLimitMiddleware {
+    async fn before(&self, _request: &mut Request) -> Result<Option<Response>> {
+        self.limiter.acquire().await?;
+        Ok(None)
+    }
+}
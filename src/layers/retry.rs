@@ -0,0 +1,348 @@
+use std::{fmt, future::Future, sync::Arc, time::Duration};
+
+use crate::{
+    agent::{Agent, CategorizedAgent, Images},
+    error::Error,
+    image::Image,
+    result::Result,
+};
+use async_trait::async_trait;
+use reqwest::StatusCode;
+use tracing::{info_span, instrument, warn, Instrument};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// How the delay grows between attempts.
+pub enum Backoff {
+    /// Same delay before every retry.
+    Constant(Duration),
+    /// `initial * multiplier^(retry - 1)`, never more than `max`.
+    Exponential {
+        initial: Duration,
+        multiplier: f64,
+        max: Duration,
+    },
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::Exponential {
+            initial: Duration::from_millis(200),
+            multiplier: 2.0,
+            max: Duration::from_secs(10),
+        }
+    }
+}
+
+impl Backoff {
+    /// Exponential backoff growing by `multiplier` from `initial` up to `max`.
+    ///
+    /// # Errors
+    /// Returns [Error::InvalidConfig] if `multiplier` is not a finite number of at least 1.
+    pub fn exponential(initial: Duration, multiplier: f64, max: Duration) -> Result<Self> {
+        if !multiplier.is_finite() || multiplier < 1.0 {
+            return Err(Error::InvalidConfig(format!(
+                "backoff multiplier must be at least 1, got {multiplier}"
+            )));
+        }
+        Ok(Self::Exponential {
+            initial,
+            multiplier,
+            max,
+        })
+    }
+
+    /// Delay before the `retry`-th retry, starting from 1.
+    ///
+    /// Delays too large for a [Duration] and invalid multipliers give `max`.
+    pub fn delay(&self, retry: u32) -> Duration {
+        match *self {
+            Self::Constant(delay) => delay,
+            Self::Exponential {
+                initial,
+                multiplier,
+                max,
+            } => {
+                let exponent = retry.saturating_sub(1).min(i32::MAX as u32) as i32;
+                let secs = initial.as_secs_f64() * multiplier.powi(exponent);
+                match Duration::try_from_secs_f64(secs) {
+                    Ok(delay) if secs.is_finite() => delay.min(max),
+                    _ => max,
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+/// Randomization of the backoff delay, so clients don't retry in lockstep.
+pub enum Jitter {
+    /// Use the delay as is.
+    None,
+    /// Random delay between zero and the backoff delay.
+    #[default]
+    Full,
+    /// Half of the backoff delay plus a random part of the other half.
+    Equal,
+}
+
+impl Jitter {
+    /// Applies the jitter to `delay`.
+    pub fn apply(&self, delay: Duration) -> Duration {
+        let random = |max: Duration| Duration::from_nanos(fastrand::u64(..=max.as_nanos() as u64));
+        match self {
+            Self::None => delay,
+            Self::Full => random(delay),
+            Self::Equal => delay / 2 + random(delay / 2),
+        }
+    }
+}
+
+/// Longest wait a `Retry-After` header can ask for, see [RetryPolicy::max_retry_after].
+pub const DEFAULT_MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+type Classifier = Arc<dyn Fn(&Error) -> bool + Send + Sync>;
+
+#[derive(Clone)]
+/// Describes when and how often failed requests are repeated.
+///
+/// By default makes 3 attempts with exponential backoff and full jitter,
+/// retrying errors accepted by [RetryPolicy::is_transient] and waiting as
+/// long as `Retry-After` asks on [Error::RateLimit], but no longer than
+/// [DEFAULT_MAX_RETRY_AFTER].
+///
+/// # Example
+/// ```rust
+/// use std::time::Duration;
+/// use anime_grubber::layers::retry::{Backoff, Jitter, Retry, RetryPolicy};
+/// use anime_grubber::agents::waifu_pics::{Waifu, Categories};
+///
+/// let policy = RetryPolicy::default()
+///     .max_attempts(5)
+///     .backoff(Backoff::Constant(Duration::from_millis(500)))
+///     .jitter(Jitter::None);
+/// let waifu = Retry::new(Waifu::new(Categories::default()), policy);
+/// ```
+pub struct RetryPolicy {
+    max_attempts: u32,
+    backoff: Backoff,
+    jitter: Jitter,
+    retry_on: Classifier,
+    respect_retry_after: bool,
+    max_retry_after: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            backoff: Backoff::default(),
+            jitter: Jitter::default(),
+            retry_on: Arc::new(Self::is_transient),
+            respect_retry_after: true,
+            max_retry_after: DEFAULT_MAX_RETRY_AFTER,
+        }
+    }
+}
+
+impl fmt::Debug for RetryPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetryPolicy")
+            .field("max_attempts", &self.max_attempts)
+            .field("backoff", &self.backoff)
+            .field("jitter", &self.jitter)
+            .field("respect_retry_after", &self.respect_retry_after)
+            .field("max_retry_after", &self.max_retry_after)
+            .finish_non_exhaustive()
+    }
+}
+
+impl RetryPolicy {
+    /// Policy which never retries.
+    pub fn never() -> Self {
+        Self::default().max_attempts(1)
+    }
+
+    /// Sets how many times a request is made at most, including the first one.
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Sets the backoff curve.
+    pub fn backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Sets the jitter applied to the backoff delay.
+    pub fn jitter(mut self, jitter: Jitter) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Sets which errors are worth another attempt.
+    pub fn retry_on(mut self, retry_on: impl Fn(&Error) -> bool + Send + Sync + 'static) -> Self {
+        self.retry_on = Arc::new(retry_on);
+        self
+    }
+
+    /// Whether to wait as long as `Retry-After` says instead of the backoff delay.
+    pub fn respect_retry_after(mut self, respect: bool) -> Self {
+        self.respect_retry_after = respect;
+        self
+    }
+
+    /// Sets the longest wait a `Retry-After` header can ask for.
+    pub fn max_retry_after(mut self, max: Duration) -> Self {
+        self.max_retry_after = max;
+        self
+    }
+
+    /// Default classification of errors worth another attempt:
+    /// timeouts, connection failures, interrupted bodies and downloads,
+    /// rate limits, `408` and `5xx` statuses.
+    pub fn is_transient(error: &Error) -> bool {
+        match error.root() {
+            Error::Reqwest(error) => {
+                error.is_timeout()
+                    || error.is_connect()
+                    || error.is_request()
+                    || error.is_body()
+                    || error.is_decode()
+            }
+            Error::RateLimit { .. } | Error::Incomplete { .. } => true,
+            Error::RequestFailed { status, .. } => {
+                status.is_server_error() || *status == StatusCode::REQUEST_TIMEOUT
+            }
+            _ => false,
+        }
+    }
+
+    /// Delay before the `retry`-th retry after `error`.
+    pub fn delay(&self, retry: u32, error: &Error) -> Duration {
+        match error.root() {
+            Error::RateLimit {
+                retry_after: Some(retry_after),
+            } if self.respect_retry_after => (*retry_after).min(self.max_retry_after),
+            _ => self.jitter.apply(self.backoff.delay(retry)),
+        }
+    }
+
+    /// Runs `operation` until it succeeds, fails with a permanent error
+    /// or runs out of attempts.
+    ///
+    /// Every attempt runs inside its own `attempt` tracing span.
+    ///
+    /// # Errors
+    /// Returns the error of the last attempt.
+    pub async fn run<T, F, Fut>(&self, mut operation: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut attempt = 1;
+        loop {
+            let span = info_span!("attempt", attempt, max_attempts = self.max_attempts);
+            let error = match operation().instrument(span).await {
+                Ok(value) => return Ok(value),
+                Err(error) => error,
+            };
+            if attempt >= self.max_attempts || !(self.retry_on)(&error) {
+                return Err(error);
+            }
+            let delay = self.delay(attempt, &error);
+            warn!("Attempt {attempt} failed: {error}, retry in {delay:?}");
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// An [Agent] retrying failed requests of the inner agent according to a [RetryPolicy].
+pub struct Retry<A> {
+    inner: A,
+    policy: RetryPolicy,
+}
+
+impl<A> Retry<A> {
+    /// Wraps `inner` with `policy`.
+    pub fn new(inner: A, policy: RetryPolicy) -> Self {
+        Self { inner, policy }
+    }
+
+    /// Returns the wrapped agent.
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    /// Returns the policy of the wrapper.
+    pub fn policy(&self) -> &RetryPolicy {
+        &self.policy
+    }
+
+    /// Unwraps the inner agent.
+    pub fn into_inner(self) -> A {
+        self.inner
+    }
+}
+
+#[async_trait]
+impl<A> Agent for Retry<A>
+where
+    A: Agent + Send + Sync,
+{
+    #[instrument(skip(self))]
+    async fn get(&self) -> Result<Image> {
+        self.policy.run(|| self.inner.get()).await
+    }
+
+    #[instrument(skip(self))]
+    async fn get_many(&self) -> Result<Images> {
+        self.policy.run(|| self.inner.get_many()).await
+    }
+
+    #[instrument(skip(self))]
+    async fn get_random(&self) -> Result<Image> {
+        self.policy.run(|| self.inner.get_random()).await
+    }
+
+    #[instrument(skip(self))]
+    async fn get_batch(&self, count: usize) -> Result<Images> {
+        self.policy.run(|| self.inner.get_batch(count)).await
+    }
+
+    fn max_batch(&self) -> usize {
+        self.inner.max_batch()
+    }
+}
+
+#[async_trait]
+impl<A> CategorizedAgent for Retry<A>
+where
+    A: CategorizedAgent + Send + Sync,
+{
+    type Category = A::Category;
+
+    fn categories() -> Vec<Self::Category> {
+        A::categories()
+    }
+
+    fn category(&self) -> Self::Category {
+        self.inner.category()
+    }
+
+    fn set_category(&mut self, category: Self::Category) {
+        self.inner.set_category(category);
+    }
+
+    #[instrument(skip(self))]
+    async fn get_in(&self, category: Self::Category) -> Result<Image> {
+        self.policy.run(|| self.inner.get_in(category)).await
+    }
+
+    #[instrument(skip(self))]
+    async fn get_many_in(&self, category: Self::Category) -> Result<Images> {
+        self.policy.run(|| self.inner.get_many_in(category)).await
+    }
+}
@@ -15,9 +15,26 @@
 /// A trait for image retrieval agents.
 pub mod agent;
 pub mod agents;
+/// HTTP resources shared between agents
+pub mod context;
+/// Downloading the files of images
+pub mod download;
 /// pub errors of this crate
 pub mod error;
 pub mod gen_enum;
 pub mod gen_url;
+/// Images returned by agents
+pub mod image;
+pub mod layers;
+/// Hooks around the requests of agents
+pub mod middleware;
+/// Proxy settings of agents
+pub mod proxy;
+/// Lookup of agents by name at runtime
+pub mod registry;
 pub mod result;
-pub use crate::{agent::Agent, agents::*, error::Error, result::Result};
+/// Mapping of HTTP statuses to [Error]s
+pub mod status;
+/// HTTP transports used by agents
+pub mod transport;
+pub use crate::{agent::Agent, agents::*, error::Error, image::Image, result::Result};
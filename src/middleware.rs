@@ -0,0 +1,233 @@
+use std::{fmt, sync::Arc};
+
+use crate::{
+    result::Result,
+    transport::{Request, Response, StreamingResponse, Transport},
+};
+use async_trait::async_trait;
+use tracing::{debug, instrument};
+
+/// Hooks running around every request of an agent.
+///
+/// Middleware is added with [AgentContext::with_middleware](crate::context::AgentContext::with_middleware)
+/// or [WaifuBuilder::middleware](crate::agents::waifu_pics::WaifuBuilder::middleware).
+/// `before` hooks run in the order the middleware was added, `after` hooks
+/// in reverse order, so the first middleware sees the request first and the
+/// response last.
+///
+/// A `before` hook may answer the request itself by returning a response.
+/// The remaining `before` hooks and the transport are skipped, while the
+/// `after` hooks of the middleware which already ran still see the response.
+/// An error of any hook fails the request.
+///
+/// # Example
+/// ```rust
+/// use anime_grubber::middleware::Middleware;
+/// use anime_grubber::transport::Request;
+/// use reqwest::header::{HeaderValue, AUTHORIZATION};
+///
+/// #[derive(Debug)]
+/// struct Auth(HeaderValue);
+///
+/// #[async_trait::async_trait]
+/// impl Middleware for Auth {
+///     async fn before(
+///         &self,
+///         request: &mut Request,
+///     ) -> anime_grubber::Result<Option<anime_grubber::transport::Response>> {
+///         request.headers.insert(AUTHORIZATION, self.0.clone());
+///         Ok(None)
+///     }
+/// }
+/// ```
+#[async_trait]
+pub trait Middleware: fmt::Debug + Send + Sync {
+    /// Runs before the request is sent, returning a response skips sending it.
+    ///
+    /// # Errors
+    /// Returned errors fail the request.
+    async fn before(&self, request: &mut Request) -> Result<Option<Response>> {
+        let _ = request;
+        Ok(None)
+    }
+
+    /// Runs after the response is received.
+    ///
+    /// Streamed responses, e.g. of downloads, are passed without their body,
+    /// changes to the body are ignored.
+    ///
+    /// # Errors
+    /// Returned errors fail the request.
+    async fn after(&self, response: &mut Response) -> Result<()> {
+        let _ = response;
+        Ok(())
+    }
+}
+
+/// Creates middleware changing every request, e.g. adding a header or rewriting the URL.
+///
+/// # Example
+/// ```rust
+/// use anime_grubber::middleware::before_fn;
+///
+/// let mirror = before_fn(|request| {
+///     request.url = request.url.replace("api.waifu.pics", "waifu.mirror.example");
+/// });
+/// ```
+pub fn before_fn<F>(hook: F) -> BeforeFn<F>
+where
+    F: Fn(&mut Request) + Send + Sync,
+{
+    BeforeFn(hook)
+}
+
+/// Creates middleware looking at or changing every response.
+pub fn after_fn<F>(hook: F) -> AfterFn<F>
+where
+    F: Fn(&mut Response) + Send + Sync,
+{
+    AfterFn(hook)
+}
+
+/// Middleware created by [before_fn].
+pub struct BeforeFn<F>(F);
+
+impl<F> fmt::Debug for BeforeFn<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("BeforeFn")
+    }
+}
+
+#[async_trait]
+impl<F> Middleware for BeforeFn<F>
+where
+    F: Fn(&mut Request) + Send + Sync,
+{
+    async fn before(&self, request: &mut Request) -> Result<Option<Response>> {
+        (self.0)(request);
+        Ok(None)
+    }
+}
+
+/// Middleware created by [after_fn].
+pub struct AfterFn<F>(F);
+
+impl<F> fmt::Debug for AfterFn<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("AfterFn")
+    }
+}
+
+#[async_trait]
+impl<F> Middleware for AfterFn<F>
+where
+    F: Fn(&mut Response) + Send + Sync,
+{
+    async fn after(&self, response: &mut Response) -> Result<()> {
+        (self.0)(response);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+/// [Transport] running requests through middleware before passing them on.
+pub struct Chain {
+    transport: Arc<dyn Transport>,
+    middleware: Vec<Arc<dyn Middleware>>,
+}
+
+impl Chain {
+    /// Wraps `transport` without any middleware.
+    pub fn new(transport: Arc<dyn Transport>) -> Self {
+        Self {
+            transport,
+            middleware: Vec::new(),
+        }
+    }
+
+    /// Appends middleware to the end of the chain.
+    pub fn with(mut self, middleware: Arc<dyn Middleware>) -> Self {
+        self.middleware.push(middleware);
+        self
+    }
+
+    /// Transport the requests are passed on to.
+    pub fn inner(&self) -> &dyn Transport {
+        self.transport.as_ref()
+    }
+
+    /// Number of middleware in the chain.
+    pub fn len(&self) -> usize {
+        self.middleware.len()
+    }
+
+    /// Whether the chain has no middleware.
+    pub fn is_empty(&self) -> bool {
+        self.middleware.is_empty()
+    }
+}
+
+impl Chain {
+    /// Runs the `before` hooks, returning how many ran and the response of the one which answered.
+    async fn before(&self, request: &mut Request) -> Result<(usize, Option<Response>)> {
+        for (ran, middleware) in self.middleware.iter().enumerate() {
+            if let Some(response) = middleware.before(request).await? {
+                debug!("{middleware:?} answered {} {}", request.method, request.url);
+                let response = match response.url.is_empty() {
+                    true => Response {
+                        url: request.url.clone(),
+                        ..response
+                    },
+                    false => response,
+                };
+                return Ok((ran + 1, Some(response)));
+            }
+        }
+        Ok((self.middleware.len(), None))
+    }
+
+    /// Runs the `after` hooks of the first `ran` middleware in reverse order.
+    async fn after(&self, ran: usize, response: &mut Response) -> Result<()> {
+        for middleware in self.middleware[..ran].iter().rev() {
+            middleware.after(response).await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Transport for Chain {
+    #[instrument(skip_all, fields(middleware = self.middleware.len()))]
+    async fn send(&self, mut request: Request) -> Result<Response> {
+        let (ran, answer) = self.before(&mut request).await?;
+        let mut response = match answer {
+            Some(response) => response,
+            None => self.transport.send(request).await?,
+        };
+        self.after(ran, &mut response).await?;
+        Ok(response)
+    }
+
+    #[instrument(skip_all, fields(middleware = self.middleware.len()))]
+    async fn send_streaming(&self, mut request: Request) -> Result<StreamingResponse> {
+        let (ran, answer) = self.before(&mut request).await?;
+        if let Some(mut response) = answer {
+            self.after(ran, &mut response).await?;
+            return Ok(response.into());
+        }
+        let streaming = self.transport.send_streaming(request).await?;
+        let mut head = Response {
+            status: streaming.status,
+            url: streaming.url,
+            headers: streaming.headers,
+            body: Vec::new(),
+        };
+        self.after(ran, &mut head).await?;
+        Ok(StreamingResponse {
+            status: head.status,
+            url: head.url,
+            headers: head.headers,
+            body: streaming.body,
+        })
+    }
+}
@@ -0,0 +1,93 @@
+use std::fmt;
+
+use crate::{error::Error, result::Result};
+use reqwest::NoProxy;
+
+#[derive(Clone, PartialEq, Eq)]
+/// Proxy every request of an agent is routed through.
+///
+/// `http://` and `https://` URLs are supported out of the box,
+/// `socks5://` and `socks5h://` ones need the `socks` feature. With `socks5h`
+/// host names are resolved by the proxy, which is what Tor expects.
+///
+/// The password is left out of the `Debug` output.
+///
+/// # Example
+/// ```rust
+/// use anime_grubber::proxy::ProxyConfig;
+/// use anime_grubber::agents::waifu_pics::Waifu;
+///
+/// let proxy = ProxyConfig::new("http://proxy.corp.example:3128")
+///     .basic_auth("user", "secret")
+///     .no_proxy(["localhost", "10.0.0.0/8", ".internal.example"]);
+/// let waifu = Waifu::builder()
+///     .proxy(proxy)
+///     .build()
+///     .expect("Failed to create HTTP client");
+/// ```
+pub struct ProxyConfig {
+    url: String,
+    auth: Option<(String, String)>,
+    no_proxy: Vec<String>,
+}
+
+impl fmt::Debug for ProxyConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let auth = self
+            .auth
+            .as_ref()
+            .map(|(username, _)| (username, "<redacted>"));
+        f.debug_struct("ProxyConfig")
+            .field("url", &self.url)
+            .field("auth", &auth)
+            .field("no_proxy", &self.no_proxy)
+            .finish()
+    }
+}
+
+impl ProxyConfig {
+    /// Routes requests through the proxy at `url`.
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            auth: None,
+            no_proxy: Vec::new(),
+        }
+    }
+
+    /// Authenticates at the proxy with a username and a password.
+    pub fn basic_auth(mut self, username: impl Into<String>, password: impl Into<String>) -> Self {
+        self.auth = Some((username.into(), password.into()));
+        self
+    }
+
+    /// Adds hosts which are reached directly.
+    ///
+    /// Entries are host names (a leading `.` matches subdomains), IP addresses
+    /// or IP ranges like `192.168.0.0/16`, as in the `NO_PROXY` variable.
+    pub fn no_proxy<I, S>(mut self, hosts: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.no_proxy.extend(hosts.into_iter().map(Into::into));
+        self
+    }
+
+    /// URL of the proxy.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Creates the reqwest proxy.
+    ///
+    /// # Errors
+    /// Returns [Error::Client] if the URL is not a valid proxy URL.
+    pub(crate) fn build(&self) -> Result<reqwest::Proxy> {
+        let mut proxy = reqwest::Proxy::all(&self.url).map_err(Error::Client)?;
+        if let Some((username, password)) = &self.auth {
+            proxy = proxy.basic_auth(username, password);
+        }
+        Ok(proxy.no_proxy(NoProxy::from_string(&self.no_proxy.join(","))))
+    }
+}
@@ -0,0 +1,153 @@
+use std::{collections::BTreeMap, fmt};
+
+use crate::{
+    agent::Agent,
+    agents::waifu_pics::{self, WaifuFactory},
+    error::Error,
+    result::Result,
+};
+use tracing::{debug, instrument};
+
+/// Agent stored in the [AgentRegistry].
+pub type BoxedAgent = Box<dyn Agent + Send + Sync>;
+
+/// Creates agents of one kind from a category string.
+///
+/// Implement it to make your own [Agent] available through [AgentRegistry::build].
+///
+/// # Example
+/// ```rust
+/// use anime_grubber::registry::{AgentFactory, AgentRegistry, BoxedAgent};
+/// use anime_grubber::agents::waifu_pics::Waifu;
+///
+/// struct MyFactory;
+///
+/// impl AgentFactory for MyFactory {
+///     fn categories(&self) -> Vec<String> {
+///         vec!["sfw/waifu".to_owned()]
+///     }
+///     fn build(&self, _category: Option<&str>) -> anime_grubber::Result<BoxedAgent> {
+///         Ok(Box::new(Waifu::try_new(Default::default())?))
+///     }
+/// }
+///
+/// let mut registry = AgentRegistry::default();
+/// registry.register("mine", MyFactory);
+/// assert!(registry.names().any(|name| name == "mine"));
+/// ```
+pub trait AgentFactory: Send + Sync {
+    /// Lists the categories accepted by [AgentFactory::build].
+    fn categories(&self) -> Vec<String>;
+    /// Creates an agent for `category`, or for the default one when `None`.
+    ///
+    /// # Errors
+    /// Returns [Error::UnknownCategory] if the category is not supported.
+    fn build(&self, category: Option<&str>) -> Result<BoxedAgent>;
+}
+
+/// Agents and agent factories looked up by name at runtime.
+///
+/// Agents are described by specs in the form `name` or `name:category`,
+/// e.g. `waifu_pics:sfw/hug`. The default registry knows every agent of this crate.
+///
+/// # Example
+/// ```rust
+/// use anime_grubber::registry::AgentRegistry;
+///
+/// let mut registry = AgentRegistry::default();
+/// let agent = registry.get_or_build("waifu_pics:sfw/hug").expect("known agent");
+/// assert_eq!(agent.max_batch(), 30);
+/// assert!(registry.categories("waifu_pics").unwrap().contains(&"sfw/hug".to_owned()));
+/// ```
+pub struct AgentRegistry {
+    factories: BTreeMap<String, Box<dyn AgentFactory>>,
+    agents: BTreeMap<String, BoxedAgent>,
+}
+
+impl Default for AgentRegistry {
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        registry.register(waifu_pics::AGENT_NAME, WaifuFactory::default());
+        registry
+    }
+}
+
+impl fmt::Debug for AgentRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AgentRegistry")
+            .field("factories", &self.factories.keys())
+            .field("agents", &self.agents.keys())
+            .finish()
+    }
+}
+
+impl AgentRegistry {
+    /// Creates a registry without any factories.
+    pub fn empty() -> Self {
+        Self {
+            factories: BTreeMap::new(),
+            agents: BTreeMap::new(),
+        }
+    }
+
+    /// Registers a factory, replacing the one with the same name.
+    pub fn register(&mut self, name: impl Into<String>, factory: impl AgentFactory + 'static) {
+        self.factories.insert(name.into(), Box::new(factory));
+    }
+
+    /// Names of the registered factories.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.factories.keys().map(String::as_str)
+    }
+
+    /// Categories of the factory registered under `name`.
+    pub fn categories(&self, name: &str) -> Option<Vec<String>> {
+        self.factories.get(name).map(|factory| factory.categories())
+    }
+
+    /// Creates a new agent from a spec like `waifu_pics:sfw/hug`.
+    ///
+    /// # Errors
+    /// - [Error::UnknownAgent] if no factory is registered under the name
+    /// - [Error::UnknownCategory] if the factory does not know the category
+    #[instrument(skip(self))]
+    pub fn build(&self, spec: &str) -> Result<BoxedAgent> {
+        let (name, category) = match spec.split_once(':') {
+            Some((name, category)) => (name, Some(category)),
+            None => (spec, None),
+        };
+        let factory = self
+            .factories
+            .get(name)
+            .ok_or_else(|| Error::UnknownAgent(name.to_owned()))?;
+        debug!("Build agent {name} for {category:?}");
+        factory.build(category)
+    }
+
+    /// Stores an agent under `key`, returning the one it replaced.
+    pub fn insert(&mut self, key: impl Into<String>, agent: BoxedAgent) -> Option<BoxedAgent> {
+        self.agents.insert(key.into(), agent)
+    }
+
+    /// Returns the agent stored under `key`.
+    pub fn get(&self, key: &str) -> Option<&(dyn Agent + Send + Sync)> {
+        self.agents.get(key).map(|agent| agent.as_ref())
+    }
+
+    /// Removes the agent stored under `key`.
+    pub fn remove(&mut self, key: &str) -> Option<BoxedAgent> {
+        self.agents.remove(key)
+    }
+
+    /// Returns the agent stored under `spec`, building and storing it first if needed.
+    ///
+    /// # Errors
+    /// Same as [AgentRegistry::build].
+    pub fn get_or_build(&mut self, spec: &str) -> Result<&(dyn Agent + Send + Sync)> {
+        if !self.agents.contains_key(spec) {
+            let agent = self.build(spec)?;
+            self.agents.insert(spec.to_owned(), agent);
+        }
+        Ok(self.agents[spec].as_ref())
+    }
+}
@@ -0,0 +1,119 @@
+use std::time::{Duration, SystemTime};
+
+use crate::{
+    error::Error,
+    result::Result,
+    transport::{Response, StreamingResponse},
+};
+use futures_util::TryStreamExt;
+use reqwest::{
+    header::{HeaderMap, RETRY_AFTER},
+    StatusCode,
+};
+use tracing::{debug, trace, warn};
+
+/// How many bytes of an error response are kept in [Error::RequestFailed].
+pub const BODY_SNIPPET_LEN: usize = 256;
+/// How much of a streamed error body is read, leaving room to finish the last character.
+const ERROR_BODY_LIMIT: usize = BODY_SNIPPET_LEN + 4;
+
+/// Turns unsuccessful responses into errors.
+///
+/// Every agent passes its responses through this function, so all of them
+/// report statuses the same way:
+/// - `404` becomes [Error::NotFound]
+/// - `429` becomes [Error::RateLimit] with the parsed `Retry-After` header
+/// - any other non-2xx status becomes [Error::RequestFailed] with the URL
+///   and the first [BODY_SNIPPET_LEN] bytes of the body
+///
+/// # Errors
+/// Returns error if the status is not successful.
+pub fn error_for_status(res: Response) -> Result<Response> {
+    let status = res.status;
+    debug!("Response received: status={}", status);
+    trace!("res -> {:#?}", res);
+    if status.is_success() {
+        return Ok(res);
+    }
+    Err(status_error(res))
+}
+
+/// Same as [error_for_status] for responses whose body was not read yet.
+///
+/// The body is only read if the status is not successful, and then only
+/// as far as needed for the snippet, the rest is dropped unread.
+///
+/// # Errors
+/// Returns error if the status is not successful.
+pub async fn error_for_streaming_status(res: StreamingResponse) -> Result<StreamingResponse> {
+    debug!("Response received: status={}", res.status);
+    if res.status.is_success() {
+        return Ok(res);
+    }
+    let mut body = Vec::new();
+    let mut stream = res.body;
+    while body.len() < ERROR_BODY_LIMIT {
+        let Some(chunk) = stream.try_next().await? else {
+            break;
+        };
+        let take = chunk.len().min(ERROR_BODY_LIMIT - body.len());
+        body.extend_from_slice(&chunk[..take]);
+    }
+    Err(status_error(Response {
+        status: res.status,
+        url: res.url,
+        headers: res.headers,
+        body,
+    }))
+}
+
+fn status_error(res: Response) -> Error {
+    warn!("Request to {} failed with status {}", res.url, res.status);
+    match res.status {
+        StatusCode::NOT_FOUND => Error::NotFound,
+        StatusCode::TOO_MANY_REQUESTS => Error::RateLimit {
+            retry_after: retry_after(&res.headers),
+        },
+        status => Error::RequestFailed {
+            status,
+            body: snippet(&res.text()).to_owned(),
+            url: res.url,
+        },
+    }
+}
+
+/// Parses the `Retry-After` header, given either in seconds or as an HTTP date.
+///
+/// # Example
+/// ```rust
+/// use std::time::Duration;
+/// use reqwest::header::{HeaderMap, HeaderValue, RETRY_AFTER};
+/// use anime_grubber::status::retry_after;
+///
+/// let mut headers = HeaderMap::new();
+/// headers.insert(RETRY_AFTER, HeaderValue::from_static("120"));
+/// assert_eq!(retry_after(&headers), Some(Duration::from_secs(120)));
+/// ```
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(
+        date.duration_since(SystemTime::now())
+            .unwrap_or(Duration::ZERO),
+    )
+}
+
+/// Cuts `body` to [BODY_SNIPPET_LEN] bytes without splitting a character.
+fn snippet(body: &str) -> &str {
+    if body.len() <= BODY_SNIPPET_LEN {
+        return body;
+    }
+    let mut end = BODY_SNIPPET_LEN;
+    while !body.is_char_boundary(end) {
+        end -= 1;
+    }
+    &body[..end]
+}
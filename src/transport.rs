@@ -0,0 +1,384 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+use crate::result::Result;
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures_util::{
+    stream::{self, BoxStream},
+    StreamExt, TryStreamExt,
+};
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue, CONTENT_LENGTH, CONTENT_TYPE},
+    Method, StatusCode,
+};
+use tracing::{debug, instrument, trace};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// An HTTP request sent through a [Transport].
+pub struct Request {
+    pub method: Method,
+    pub url: String,
+    pub headers: HeaderMap,
+    pub body: Option<Vec<u8>>,
+}
+
+impl Request {
+    /// Creates a request without headers and body.
+    pub fn new(method: Method, url: impl Into<String>) -> Self {
+        Self {
+            method,
+            url: url.into(),
+            headers: HeaderMap::new(),
+            body: None,
+        }
+    }
+
+    /// Creates a `GET` request.
+    pub fn get(url: impl Into<String>) -> Self {
+        Self::new(Method::GET, url)
+    }
+
+    /// Creates a `POST` request.
+    pub fn post(url: impl Into<String>) -> Self {
+        Self::new(Method::POST, url)
+    }
+
+    /// Adds a header, replacing the one with the same name.
+    pub fn header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.insert(name, value);
+        self
+    }
+
+    /// Sets the body.
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = Some(body.into());
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// An HTTP response received from a [Transport].
+pub struct Response {
+    pub status: StatusCode,
+    /// Final URL of the response, after redirects.
+    pub url: String,
+    pub headers: HeaderMap,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    /// Creates an empty response with the given status.
+    pub fn new(status: StatusCode) -> Self {
+        Self {
+            status,
+            url: String::new(),
+            headers: HeaderMap::new(),
+            body: Vec::new(),
+        }
+    }
+
+    /// Creates a `200` response with a JSON body.
+    pub fn json(body: impl Into<String>) -> Self {
+        Self::new(StatusCode::OK)
+            .header(CONTENT_TYPE, HeaderValue::from_static("application/json"))
+            .body(body.into())
+    }
+
+    /// Adds a header, replacing the one with the same name.
+    pub fn header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.insert(name, value);
+        self
+    }
+
+    /// Sets the body.
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Body decoded as UTF-8, invalid sequences are replaced.
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+}
+
+/// Body of a [StreamingResponse], read chunk by chunk.
+pub type ByteStream = BoxStream<'static, Result<Bytes>>;
+
+/// An HTTP response whose body is read while it arrives.
+pub struct StreamingResponse {
+    pub status: StatusCode,
+    /// Final URL of the response, after redirects.
+    pub url: String,
+    pub headers: HeaderMap,
+    pub body: ByteStream,
+}
+
+impl fmt::Debug for StreamingResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StreamingResponse")
+            .field("status", &self.status)
+            .field("url", &self.url)
+            .field("headers", &self.headers)
+            .finish_non_exhaustive()
+    }
+}
+
+impl StreamingResponse {
+    /// Length of the body announced in the `Content-Length` header.
+    pub fn content_length(&self) -> Option<u64> {
+        self.headers
+            .get(CONTENT_LENGTH)?
+            .to_str()
+            .ok()?
+            .parse()
+            .ok()
+    }
+
+    /// Reads the whole body.
+    ///
+    /// # Errors
+    /// Returns the first error of the body stream.
+    pub async fn collect(self) -> Result<Response> {
+        let chunks: Vec<Bytes> = self.body.try_collect().await?;
+        Ok(Response {
+            status: self.status,
+            url: self.url,
+            headers: self.headers,
+            body: chunks.concat(),
+        })
+    }
+}
+
+impl From<Response> for StreamingResponse {
+    fn from(response: Response) -> Self {
+        let body = match response.body.is_empty() {
+            true => stream::empty().boxed(),
+            false => stream::once(async move { Ok(Bytes::from(response.body)) }).boxed(),
+        };
+        Self {
+            status: response.status,
+            url: response.url,
+            headers: response.headers,
+            body,
+        }
+    }
+}
+
+/// Sends HTTP requests for agents.
+///
+/// Agents never talk to an HTTP library directly, so any client can be
+/// plugged in through [AgentContext::with_transport](crate::context::AgentContext::with_transport).
+/// [ReqwestTransport] is used by default, [MemoryTransport] answers
+/// with canned responses and is meant for tests.
+///
+/// Transports report network failures as errors. Unsuccessful statuses are
+/// regular responses, agents map them with [error_for_status](crate::status::error_for_status).
+#[async_trait]
+pub trait Transport: fmt::Debug + Send + Sync {
+    /// Sends `request` and reads the whole response.
+    ///
+    /// # Errors
+    /// Returns error if the request could not be sent or the response not read.
+    async fn send(&self, request: Request) -> Result<Response>;
+
+    /// Sends `request` and returns the response before its body is read.
+    ///
+    /// The default implementation reads the whole body with [Transport::send].
+    ///
+    /// # Errors
+    /// Returns error if the request could not be sent.
+    async fn send_streaming(&self, request: Request) -> Result<StreamingResponse> {
+        Ok(self.send(request).await?.into())
+    }
+}
+
+#[async_trait]
+impl<T: Transport + ?Sized> Transport for Arc<T> {
+    async fn send(&self, request: Request) -> Result<Response> {
+        self.as_ref().send(request).await
+    }
+
+    async fn send_streaming(&self, request: Request) -> Result<StreamingResponse> {
+        self.as_ref().send_streaming(request).await
+    }
+}
+
+#[derive(Debug, Clone)]
+/// [Transport] backed by a [reqwest::Client].
+pub struct ReqwestTransport {
+    client: reqwest::Client,
+}
+
+impl ReqwestTransport {
+    /// Wraps a pre-configured client.
+    pub fn new(client: reqwest::Client) -> Self {
+        Self { client }
+    }
+
+    /// HTTP client of the transport.
+    pub fn client(&self) -> &reqwest::Client {
+        &self.client
+    }
+
+    fn request(&self, request: Request) -> reqwest::RequestBuilder {
+        let builder = self
+            .client
+            .request(request.method, request.url)
+            .headers(request.headers);
+        match request.body {
+            Some(body) => builder.body(body),
+            None => builder,
+        }
+    }
+}
+
+#[async_trait]
+impl Transport for ReqwestTransport {
+    #[instrument(skip(self, request), fields(method = %request.method, url = %request.url))]
+    async fn send(&self, request: Request) -> Result<Response> {
+        let res = self.request(request).send().await?;
+        let status = res.status();
+        let url = res.url().to_string();
+        let headers = res.headers().clone();
+        let body = res.bytes().await?.to_vec();
+        Ok(Response {
+            status,
+            url,
+            headers,
+            body,
+        })
+    }
+
+    #[instrument(skip(self, request), fields(method = %request.method, url = %request.url))]
+    async fn send_streaming(&self, request: Request) -> Result<StreamingResponse> {
+        let res = self.request(request).send().await?;
+        Ok(StreamingResponse {
+            status: res.status(),
+            url: res.url().to_string(),
+            headers: res.headers().clone(),
+            body: res.bytes_stream().err_into().boxed(),
+        })
+    }
+}
+
+type Handler = dyn Fn(&Request) -> Result<Response> + Send + Sync;
+
+#[derive(Clone, Default)]
+/// [Transport] answering from memory, for tests and offline use.
+///
+/// Responses are looked up by method and URL. Requests without a canned
+/// response are passed to the fallback handler, or answered with `404`.
+/// Every request is recorded, clones share responses and records.
+///
+/// # Example
+/// ```rust
+/// use anime_grubber::agent::Agent;
+/// use anime_grubber::agents::waifu_pics::{Waifu, Categories, SFW};
+/// use anime_grubber::context::AgentContext;
+/// use anime_grubber::transport::{MemoryTransport, Response};
+///
+/// # async fn example() -> anime_grubber::Result<()> {
+/// let transport = MemoryTransport::new().get(
+///     "https://api.waifu.pics/sfw/hug",
+///     Response::json(r#"{"url":"https://i.waifu.pics/hug.gif"}"#),
+/// );
+/// let waifu = Waifu::builder()
+///     .categorie(Categories::SFW(SFW::Hug))
+///     .context(&AgentContext::with_transport(transport.clone()))
+///     .build()?;
+/// assert_eq!(waifu.get().await?.url, "https://i.waifu.pics/hug.gif");
+/// assert_eq!(transport.requests().len(), 1);
+/// # Ok(())
+/// # }
+/// ```
+pub struct MemoryTransport {
+    responses: Arc<Mutex<HashMap<(Method, String), Response>>>,
+    fallback: Option<Arc<Handler>>,
+    requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl fmt::Debug for MemoryTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemoryTransport")
+            .field("responses", &self.responses.lock().expect("poisoned").len())
+            .field("fallback", &self.fallback.is_some())
+            .field("requests", &self.requests.lock().expect("poisoned").len())
+            .finish()
+    }
+}
+
+impl MemoryTransport {
+    /// Creates a transport without responses.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a transport answering every request with `handler`.
+    pub fn from_fn<F>(handler: F) -> Self
+    where
+        F: Fn(&Request) -> Result<Response> + Send + Sync + 'static,
+    {
+        Self {
+            fallback: Some(Arc::new(handler)),
+            ..Self::default()
+        }
+    }
+
+    /// Answers requests with `method` to `url` with `response`.
+    pub fn respond(self, method: Method, url: impl Into<String>, response: Response) -> Self {
+        self.responses
+            .lock()
+            .expect("poisoned responses")
+            .insert((method, url.into()), response);
+        self
+    }
+
+    /// Answers `GET` requests to `url` with `response`.
+    pub fn get(self, url: impl Into<String>, response: Response) -> Self {
+        self.respond(Method::GET, url, response)
+    }
+
+    /// Answers `POST` requests to `url` with `response`.
+    pub fn post(self, url: impl Into<String>, response: Response) -> Self {
+        self.respond(Method::POST, url, response)
+    }
+
+    /// Requests sent so far.
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().expect("poisoned requests").clone()
+    }
+}
+
+#[async_trait]
+impl Transport for MemoryTransport {
+    async fn send(&self, request: Request) -> Result<Response> {
+        trace!("req -> {:#?}", request);
+        self.requests
+            .lock()
+            .expect("poisoned requests")
+            .push(request.clone());
+        let canned = self
+            .responses
+            .lock()
+            .expect("poisoned responses")
+            .get(&(request.method.clone(), request.url.clone()))
+            .cloned();
+        let response = match (canned, &self.fallback) {
+            (Some(response), _) => response,
+            (None, Some(handler)) => handler(&request)?,
+            (None, None) => {
+                debug!("No response for {} {}", request.method, request.url);
+                Response::new(StatusCode::NOT_FOUND)
+            }
+        };
+        Ok(Response {
+            url: request.url,
+            ..response
+        })
+    }
+}
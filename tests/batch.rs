@@ -0,0 +1,65 @@
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use anime_grubber::{
+        agent::{Agent, Images},
+        image::Image,
+        Result,
+    };
+    use async_trait::async_trait;
+
+    /// Returns `[n, n + 1]` on the n-th call.
+    #[derive(Default)]
+    struct Counting {
+        calls: AtomicUsize,
+    }
+
+    fn image(i: usize) -> Image {
+        Image::new(format!("https://example.com/{i}.png"), "counting", "test")
+    }
+
+    #[async_trait]
+    impl Agent for Counting {
+        async fn get(&self) -> Result<Image> {
+            Ok(image(0))
+        }
+        async fn get_many(&self) -> Result<Images> {
+            let n = self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok([image(n), image(n + 1)].into())
+        }
+        async fn get_random(&self) -> Result<Image> {
+            self.get().await
+        }
+        fn max_batch(&self) -> usize {
+            2
+        }
+    }
+
+    #[tokio::test]
+    async fn default_batch_deduplicates() -> anyhow::Result<()> {
+        let agent = Counting::default();
+        let images = agent.get_batch(4).await?;
+        let urls: Vec<&str> = images.iter().map(|image| image.url.as_str()).collect();
+        assert_eq!(
+            urls,
+            [
+                "https://example.com/0.png",
+                "https://example.com/1.png",
+                "https://example.com/2.png",
+                "https://example.com/3.png",
+            ]
+        );
+        assert_eq!(agent.calls.load(Ordering::SeqCst), 3);
+        assert_eq!(agent.max_batch(), 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn zero_count_does_nothing() -> anyhow::Result<()> {
+        let agent = Counting::default();
+        assert!(agent.get_batch(0).await?.is_empty());
+        assert_eq!(agent.calls.load(Ordering::SeqCst), 0);
+        Ok(())
+    }
+}
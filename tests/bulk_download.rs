@@ -0,0 +1,223 @@
+mod common;
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        time::Duration,
+    };
+
+    use crate::common::fs::TempDir;
+    use anime_grubber::{
+        context::AgentContext,
+        download::{
+            bulk::{BulkDownloader, CancelToken, Progress, Summary},
+            manager::DownloadManager,
+            Downloader,
+        },
+        image::Image,
+        transport::{self, Request, StreamingResponse, Transport},
+        Error, Result,
+    };
+    use async_trait::async_trait;
+    use bytes::Bytes;
+    use futures_util::{stream, StreamExt};
+    use reqwest::{
+        header::{HeaderValue, CONTENT_LENGTH},
+        StatusCode,
+    };
+
+    /// Answers after `delay` with `chunks` chunks of 4 bytes, each after `delay`.
+    /// URLs containing `missing` get `404`.
+    #[derive(Debug, Default)]
+    struct Slow {
+        delay: Duration,
+        chunks: usize,
+        in_flight: AtomicUsize,
+        max_in_flight: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl Transport for Slow {
+        async fn send(&self, request: Request) -> Result<transport::Response> {
+            self.send_streaming(request).await?.collect().await
+        }
+
+        async fn send_streaming(&self, request: Request) -> Result<StreamingResponse> {
+            let running = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_in_flight.fetch_max(running, Ordering::SeqCst);
+            tokio::time::sleep(self.delay).await;
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+
+            if request.url.contains("missing") {
+                return Ok(transport::Response::new(StatusCode::NOT_FOUND).into());
+            }
+            let delay = self.delay;
+            let body = stream::iter(0..self.chunks)
+                .then(move |_| async move {
+                    tokio::time::sleep(delay).await;
+                    Ok(Bytes::from_static(b"data"))
+                })
+                .boxed();
+            let mut headers = reqwest::header::HeaderMap::new();
+            headers.insert(CONTENT_LENGTH, HeaderValue::from(self.chunks * 4));
+            Ok(StreamingResponse {
+                status: StatusCode::OK,
+                url: request.url,
+                headers,
+                body,
+            })
+        }
+    }
+
+    fn images(names: &[&str]) -> Vec<Image> {
+        names
+            .iter()
+            .map(|name| {
+                Image::new(format!("https://i.waifu.pics/{name}"), "waifu_pics", "sfw")
+                    .with_aspect("waifu")
+            })
+            .collect()
+    }
+
+    fn numbered(count: usize) -> Vec<Image> {
+        let names: Vec<String> = (0..count).map(|n| format!("{n}.png")).collect();
+        images(&names.iter().map(String::as_str).collect::<Vec<_>>())
+    }
+
+    fn bulk(dir: &TempDir, transport: Arc<Slow>) -> BulkDownloader {
+        let downloader = Downloader::new(AgentContext::with_transport(transport));
+        BulkDownloader::new(DownloadManager::new(&dir.0, downloader).template("{filename}"))
+    }
+
+    fn slow(delay: u64, chunks: usize) -> Arc<Slow> {
+        Arc::new(Slow {
+            delay: Duration::from_millis(delay),
+            chunks,
+            ..Slow::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn bounds_concurrency() -> anyhow::Result<()> {
+        let dir = TempDir::new();
+        let transport = slow(10, 1);
+        let report = bulk(&dir, transport.clone())
+            .concurrency(3)
+            .run(numbered(10))
+            .await;
+        assert!(report.is_success());
+        assert_eq!(report.saved.len(), 10);
+        assert_eq!(transport.max_in_flight.load(Ordering::SeqCst), 3);
+        assert_eq!(dir.files().len(), 10);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reports_progress() -> anyhow::Result<()> {
+        let dir = TempDir::new();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let report = bulk(&dir, slow(1, 2))
+            .on_progress({
+                let events = events.clone();
+                move |event| {
+                    let event = match event {
+                        Progress::Started { .. } => "started".to_owned(),
+                        Progress::Received { read, total, .. } => {
+                            format!("received {read}/{}", total.unwrap())
+                        }
+                        Progress::Saved { .. } => "saved".to_owned(),
+                        Progress::Failed { .. } => "failed".to_owned(),
+                        Progress::Overall(summary) => {
+                            format!("overall {}/{}", summary.finished(), summary.total)
+                        }
+                    };
+                    events.lock().unwrap().push(event);
+                }
+            })
+            .concurrency(1)
+            .run(images(&["a.png", "missing.png"]))
+            .await;
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                "started",
+                "received 4/8",
+                "received 8/8",
+                "saved",
+                "overall 1/2",
+                "started",
+                "failed",
+                "overall 2/2",
+            ]
+        );
+        assert_eq!(
+            report.summary,
+            Summary {
+                total: 2,
+                saved: 1,
+                skipped: 0,
+                failed: 1,
+                bytes: 8,
+            }
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn collects_failures() -> anyhow::Result<()> {
+        let dir = TempDir::new();
+        let report = bulk(&dir, slow(1, 1))
+            .run(images(&[
+                "a.png",
+                "missing-1.png",
+                "b.png",
+                "missing-2.png",
+            ]))
+            .await;
+        assert!(!report.is_success());
+        assert_eq!(report.saved.len(), 2);
+        let mut failed: Vec<&str> = report
+            .failed
+            .iter()
+            .map(|(image, error)| {
+                assert!(matches!(error, Error::NotFound));
+                image.file_name()
+            })
+            .collect();
+        failed.sort();
+        assert_eq!(failed, ["missing-1.png", "missing-2.png"]);
+
+        let again = bulk(&dir, slow(1, 1)).run(images(&["a.png"])).await;
+        assert_eq!(again.summary.skipped, 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn cancels_run() -> anyhow::Result<()> {
+        let dir = TempDir::new();
+        let cancel = CancelToken::new();
+        let bulk = bulk(&dir, slow(20, 5))
+            .concurrency(2)
+            .with_cancel_token(cancel.clone());
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            cancel.cancel();
+        });
+        let report = bulk.run(numbered(6)).await;
+
+        assert!(report.saved.is_empty());
+        assert_eq!(report.failed.len(), 6);
+        assert!(report
+            .failed
+            .iter()
+            .all(|(_, error)| matches!(error, Error::Cancelled)));
+        assert!(dir.files().is_empty());
+        assert!(bulk.cancel_token().is_cancelled());
+        Ok(())
+    }
+}
@@ -0,0 +1,134 @@
+mod common;
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use crate::common::agent::Counter;
+    use anime_grubber::{
+        agent::{Agent, CategorizedAgent},
+        agents::waifu_pics::{Categories, NSFW, SFW},
+        layers::cache::{CacheStats, Cached},
+    };
+
+    const HUG: Categories = Categories::SFW(SFW::Hug);
+    const PAT: Categories = Categories::SFW(SFW::Pat);
+    const NEKO: Categories = Categories::NSFW(NSFW::Neko);
+
+    #[tokio::test(start_paused = true)]
+    async fn hits_until_expired() -> anyhow::Result<()> {
+        let agent = Cached::new(Counter::new(3)).ttl(Duration::from_secs(10));
+        let first = agent.get_many().await?;
+        let second = agent.get_many().await?;
+        assert_eq!(first, second);
+        assert_eq!(agent.inner().calls(), 1);
+
+        tokio::time::advance(Duration::from_secs(11)).await;
+        let third = agent.get_many().await?;
+        assert_ne!(first, third);
+        assert_eq!(agent.inner().calls(), 2);
+        assert_eq!(
+            agent.stats(),
+            CacheStats {
+                hits: 1,
+                misses: 2,
+                ..CacheStats::default()
+            }
+        );
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn caches_per_category() -> anyhow::Result<()> {
+        let agent = Cached::new(Counter::new(2));
+        let hug = agent.get_many_in(HUG).await?;
+        let neko = agent.get_many_in(NEKO).await?;
+        assert!(hug.iter().all(|image| image.category_path() == HUG.path()));
+        assert!(neko
+            .iter()
+            .all(|image| image.category_path() == NEKO.path()));
+
+        let mut clone = agent.clone();
+        clone.set_category(NEKO);
+        assert_eq!(clone.get_many().await?, neko);
+        assert_eq!(agent.inner().calls(), 2);
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn batch_bypasses_cache() -> anyhow::Result<()> {
+        let agent = Cached::new(Counter::new(3));
+        agent.get_many().await?;
+        assert_eq!(agent.get_batch(10).await?.len(), 10);
+        assert_eq!(agent.inner().calls(), 5);
+
+        let mut clone = agent.clone();
+        clone.set_category(NEKO);
+        let neko = clone.get_batch(5).await?;
+        assert_eq!(neko.len(), 5);
+        assert!(neko
+            .iter()
+            .all(|image| image.category_path() == NEKO.path()));
+        assert_eq!(agent.stats().hits, 0);
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn evicts_least_recently_used() -> anyhow::Result<()> {
+        let agent = Cached::new(Counter::new(1)).capacity(2);
+        agent.get_many_in(HUG).await?;
+        agent.get_many_in(PAT).await?;
+        tokio::time::advance(Duration::from_millis(1)).await;
+        agent.get_many_in(HUG).await?;
+        tokio::time::advance(Duration::from_millis(1)).await;
+        agent.get_many_in(NEKO).await?;
+        assert_eq!(agent.stats().evictions, 1);
+        assert_eq!(agent.inner().calls(), 3);
+
+        agent.get_many_in(HUG).await?;
+        assert_eq!(agent.inner().calls(), 3);
+        agent.get_many_in(PAT).await?;
+        assert_eq!(agent.inner().calls(), 4);
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn serves_stale_while_revalidating() -> anyhow::Result<()> {
+        let agent = Cached::new(Counter::new(2).with_delay(Duration::from_millis(50)))
+            .ttl(Duration::from_secs(10))
+            .stale_while_revalidate(Duration::from_secs(5));
+        let first = agent.get_many().await?;
+
+        tokio::time::advance(Duration::from_secs(12)).await;
+        assert_eq!(agent.get_many().await?, first);
+        assert_eq!(agent.get_many().await?, first);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(agent.inner().calls(), 2);
+
+        let refreshed = agent.get_many().await?;
+        assert_ne!(refreshed, first);
+        assert_eq!(agent.stats().stale_hits, 2);
+        assert_eq!(agent.stats().hits, 1);
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn failed_revalidation_keeps_entry() -> anyhow::Result<()> {
+        let agent = Cached::new(Counter::new(1))
+            .ttl(Duration::from_secs(10))
+            .stale_while_revalidate(Duration::from_secs(5));
+        let first = agent.get_many().await?;
+        agent.inner().set_failing(true);
+
+        tokio::time::advance(Duration::from_secs(11)).await;
+        assert_eq!(agent.get_many().await?, first);
+        tokio::time::sleep(Duration::from_millis(1)).await;
+        assert_eq!(agent.get_many().await?, first);
+        tokio::time::sleep(Duration::from_millis(1)).await;
+        assert_eq!(agent.inner().calls(), 3);
+
+        tokio::time::advance(Duration::from_secs(4)).await;
+        assert!(agent.get_many().await.is_err());
+        Ok(())
+    }
+}
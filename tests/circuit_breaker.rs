@@ -0,0 +1,173 @@
+mod common;
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use crate::common::agent::Counter;
+    use anime_grubber::{
+        agent::Agent,
+        layers::circuit_breaker::{CircuitBreaker, Health},
+        Error,
+    };
+    use reqwest::StatusCode;
+
+    fn breaker() -> CircuitBreaker<Counter> {
+        CircuitBreaker::new(Counter::new(1))
+            .failure_threshold(2)
+            .open_for(Duration::from_secs(10))
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn opens_after_consecutive_failures() {
+        let agent = breaker();
+        agent.inner().set_failing(true);
+        assert!(matches!(
+            agent.get().await,
+            Err(Error::RequestFailed { .. })
+        ));
+        assert_eq!(agent.health(), Health::Closed);
+        assert!(matches!(
+            agent.get().await,
+            Err(Error::RequestFailed { .. })
+        ));
+        assert_eq!(agent.health(), Health::Open);
+
+        match agent.get().await {
+            Err(Error::CircuitOpen { retry_after }) => {
+                assert_eq!(retry_after, Duration::from_secs(10))
+            }
+            other => panic!("unexpected {other:?}"),
+        }
+        assert_eq!(agent.inner().calls(), 2);
+        let report = agent.report();
+        assert_eq!(report.failures, 2);
+        assert_eq!(report.rejected, 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn success_resets_failures() -> anyhow::Result<()> {
+        let agent = breaker();
+        agent.inner().set_failing(true);
+        let _ = agent.get().await;
+        agent.inner().set_failing(false);
+        agent.get().await?;
+        agent.inner().set_failing(true);
+        let _ = agent.get().await;
+        assert_eq!(agent.health(), Health::Closed);
+        assert_eq!(agent.report().consecutive_failures, 1);
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn half_open_probe_closes() -> anyhow::Result<()> {
+        let agent = breaker();
+        agent.inner().set_failing(true);
+        let _ = agent.get().await;
+        let _ = agent.get().await;
+
+        tokio::time::advance(Duration::from_secs(10)).await;
+        assert_eq!(agent.health(), Health::HalfOpen);
+        agent.inner().set_failing(false);
+        agent.get().await?;
+        assert_eq!(agent.health(), Health::Closed);
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn failed_probe_reopens() {
+        let agent = breaker();
+        let clone = agent.clone();
+        agent.inner().set_failing(true);
+        let _ = agent.get().await;
+        let _ = agent.get().await;
+
+        tokio::time::advance(Duration::from_secs(10)).await;
+        assert!(matches!(
+            clone.get().await,
+            Err(Error::RequestFailed { .. })
+        ));
+        assert_eq!(agent.health(), Health::Open);
+        assert!(matches!(agent.get().await, Err(Error::CircuitOpen { .. })));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn half_open_limits_probes() {
+        let agent = CircuitBreaker::new(Counter::new(1).with_delay(Duration::from_secs(1)))
+            .failure_threshold(1)
+            .open_for(Duration::from_secs(10));
+        agent.inner().set_failing(true);
+        let _ = agent.get().await;
+
+        tokio::time::advance(Duration::from_secs(10)).await;
+        agent.inner().set_failing(false);
+        let (first, second) = tokio::join!(agent.get(), agent.get());
+        assert!(first.is_ok());
+        assert!(matches!(second, Err(Error::CircuitOpen { .. })));
+        assert_eq!(<&str>::from(&agent.health()), "Closed");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn late_success_keeps_breaker_open() {
+        let agent = breaker();
+        let unavailable = || Error::RequestFailed {
+            status: StatusCode::SERVICE_UNAVAILABLE,
+            url: "https://example.com".to_owned(),
+            body: String::new(),
+        };
+        let slow = agent.call(async {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            Ok(())
+        });
+        let trip = async {
+            for _ in 0..2 {
+                let _ = agent.call(async { Err::<(), _>(unavailable()) }).await;
+            }
+        };
+        let (slow, ()) = tokio::join!(slow, trip);
+        assert!(slow.is_ok());
+        assert_eq!(agent.health(), Health::Open);
+        assert_eq!(agent.report().successes, 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn late_failure_keeps_probe() {
+        let agent = breaker();
+        let unavailable = || Error::RequestFailed {
+            status: StatusCode::SERVICE_UNAVAILABLE,
+            url: "https://example.com".to_owned(),
+            body: String::new(),
+        };
+        let slow = agent.call(async {
+            tokio::time::sleep(Duration::from_secs(11)).await;
+            Err::<(), _>(unavailable())
+        });
+        let trip_then_probe = async {
+            for _ in 0..2 {
+                let _ = agent.call(async { Err::<(), _>(unavailable()) }).await;
+            }
+            tokio::time::sleep(Duration::from_secs(10)).await;
+            assert_eq!(agent.health(), Health::HalfOpen);
+            agent
+                .call(async {
+                    tokio::time::sleep(Duration::from_secs(2)).await;
+                    Ok(())
+                })
+                .await
+        };
+        let (slow, probe) = tokio::join!(slow, trip_then_probe);
+        assert!(slow.is_err());
+        assert!(probe.is_ok());
+        assert_eq!(agent.health(), Health::Closed);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn custom_failure_classifier() {
+        let agent = breaker().failure_on(|_| false);
+        agent.inner().set_failing(true);
+        for _ in 0..5 {
+            let _ = agent.get().await;
+        }
+        assert_eq!(agent.health(), Health::Closed);
+    }
+}
@@ -0,0 +1,150 @@
+mod common;
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use crate::common::{agent::Counter, MockServer, Response};
+    use anime_grubber::{
+        agent::{Agent, CategorizedAgent, Images},
+        agents::waifu_pics::{Categories, Waifu, NSFW, SFW},
+        layers::{coalesce::Coalesced, retry::RetryPolicy},
+        Error,
+    };
+    use tokio::task::JoinSet;
+
+    async fn get_many_concurrently<A>(agent: &A, count: usize) -> Vec<anime_grubber::Result<Images>>
+    where
+        A: Agent + Clone + Send + Sync + 'static,
+    {
+        let mut tasks = JoinSet::new();
+        for _ in 0..count {
+            let agent = agent.clone();
+            tasks.spawn(async move { agent.get_many().await });
+        }
+        tasks.join_all().await
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn concurrent_calls_share_request() -> anyhow::Result<()> {
+        let agent = Coalesced::new(Counter::new(3).with_delay(Duration::from_millis(10)));
+        let results = get_many_concurrently(&agent, 50).await;
+        assert_eq!(agent.inner().calls(), 1);
+        let first = results[0].as_ref().unwrap();
+        assert!(results
+            .iter()
+            .all(|images| images.as_ref().unwrap() == first));
+        assert_eq!(agent.in_flight(), 0);
+
+        agent.get_many().await?;
+        assert_eq!(agent.inner().calls(), 2);
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn categories_are_separate() -> anyhow::Result<()> {
+        let agent = Coalesced::new(Counter::new(1).with_delay(Duration::from_millis(10)));
+        let hug = Categories::SFW(SFW::Hug);
+        let neko = Categories::NSFW(NSFW::Neko);
+        let (a, b, c) = tokio::join!(
+            agent.get_many_in(hug),
+            agent.get_many_in(neko),
+            agent.get_many_in(hug)
+        );
+        assert_eq!(a?, c?);
+        assert_eq!(b?[0].category_path(), neko.path());
+        assert_eq!(agent.inner().calls(), 2);
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn waiters_receive_same_error() -> anyhow::Result<()> {
+        let agent = Coalesced::new(Counter::new(1).with_delay(Duration::from_millis(10)));
+        agent.inner().set_failing(true);
+        let (a, b) = tokio::join!(agent.get_many(), agent.get_many());
+        let (Err(a), Err(b)) = (a, b) else {
+            panic!("expected errors");
+        };
+        assert!(matches!(a.root(), Error::RequestFailed { .. }));
+        assert!(matches!(b.root(), Error::RequestFailed { .. }));
+        assert!(matches!(a, Error::Shared(_)) || matches!(b, Error::Shared(_)));
+        assert!(RetryPolicy::is_transient(&a));
+        assert!(RetryPolicy::is_transient(&b));
+        assert_eq!(agent.inner().calls(), 1);
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn unshared_request_keeps_error() {
+        let agent = Coalesced::new(Counter::new(1));
+        agent.inner().set_failing(true);
+        assert!(matches!(
+            agent.get_many().await,
+            Err(Error::RequestFailed { .. })
+        ));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn get_many_joins_own_category() -> anyhow::Result<()> {
+        let agent = Coalesced::new(Counter::new(1).with_delay(Duration::from_millis(10)));
+        let (a, b) = tokio::join!(agent.get_many(), agent.get_many_in(agent.category()));
+        assert_eq!(a?, b?);
+        assert_eq!(agent.inner().calls(), 1);
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn cancelled_leader_hands_over() -> anyhow::Result<()> {
+        let agent = Coalesced::new(Counter::new(1).with_delay(Duration::from_millis(10)));
+        let leader = agent.clone();
+        let task = tokio::spawn(async move { leader.get_many().await.map(|_| ()) });
+        tokio::time::sleep(Duration::from_millis(1)).await;
+        let follower = tokio::spawn({
+            let agent = agent.clone();
+            async move { agent.get_many().await }
+        });
+        tokio::time::sleep(Duration::from_millis(1)).await;
+        task.abort();
+        assert!(follower.await?.is_ok());
+        assert_eq!(agent.inner().calls(), 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn waifu_sends_one_post() -> anyhow::Result<()> {
+        let server = MockServer::start(|_| {
+            Response::json(r#"{"files":["https://i.waifu.pics/a.gif"]}"#)
+                .delay(Duration::from_millis(50))
+        })
+        .await;
+        let agent = Coalesced::new(Waifu::new(Categories::default()).with_base_url(server.url()));
+        let results = get_many_concurrently(&agent, 20).await;
+        assert!(results.iter().all(Result::is_ok));
+        assert_eq!(server.requests().len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn batch_uses_inner_batching() -> anyhow::Result<()> {
+        let calls = AtomicUsize::new(0);
+        let server = MockServer::start(move |_| {
+            let n = calls.fetch_add(1, Ordering::SeqCst);
+            Response::json(format!(
+                r#"{{"files":["https://i.waifu.pics/{}.gif","https://i.waifu.pics/{}.gif"]}}"#,
+                2 * n,
+                2 * n + 1
+            ))
+        })
+        .await;
+        let agent = Coalesced::new(Waifu::new(Categories::default()).with_base_url(server.url()));
+        assert_eq!(agent.get_batch(4).await?.len(), 4);
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[1].body_str().contains("0.gif"));
+        Ok(())
+    }
+}
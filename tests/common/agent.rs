@@ -0,0 +1,122 @@
+//! In-memory agent counting its requests.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use anime_grubber::{
+    agent::{Agent, CategorizedAgent, Images},
+    agents::waifu_pics::Categories,
+    image::Image,
+    Error, Result,
+};
+use async_trait::async_trait;
+use reqwest::StatusCode;
+use tokio::time::Instant;
+
+/// Returns new images on every request, `batch` of them from `get_many`.
+///
+/// Clones share counters and the failing switch.
+#[derive(Debug, Default, Clone)]
+pub struct Counter {
+    pub category: Categories,
+    pub batch: usize,
+    pub delay: Duration,
+    failing: Arc<AtomicBool>,
+    calls: Arc<AtomicUsize>,
+    started: Arc<Mutex<Vec<Instant>>>,
+    images: Arc<AtomicUsize>,
+}
+
+impl Counter {
+    pub fn new(batch: usize) -> Self {
+        Self {
+            batch,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    pub fn calls(&self) -> usize {
+        self.calls.load(Ordering::SeqCst)
+    }
+
+    /// When each request started.
+    pub fn started(&self) -> Vec<Instant> {
+        self.started.lock().unwrap().clone()
+    }
+
+    pub fn set_failing(&self, failing: bool) {
+        self.failing.store(failing, Ordering::SeqCst);
+    }
+
+    async fn request(&self, category: Categories, count: usize) -> Result<Images> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        self.started.lock().unwrap().push(Instant::now());
+        if !self.delay.is_zero() {
+            tokio::time::sleep(self.delay).await;
+        }
+        if self.failing.load(Ordering::SeqCst) {
+            return Err(Error::RequestFailed {
+                status: StatusCode::SERVICE_UNAVAILABLE,
+                url: "https://example.com".to_owned(),
+                body: String::new(),
+            });
+        }
+        Ok((0..count)
+            .map(|_| {
+                let n = self.images.fetch_add(1, Ordering::SeqCst);
+                let url = format!("https://example.com/{}/{n}.png", category.path());
+                let main: &str = (&category).into();
+                Image::new(url, "counter", main.to_lowercase())
+                    .with_aspect(category.nested_str().to_lowercase())
+            })
+            .collect())
+    }
+}
+
+#[async_trait]
+impl Agent for Counter {
+    async fn get(&self) -> Result<Image> {
+        self.get_in(self.category).await
+    }
+    async fn get_many(&self) -> Result<Images> {
+        self.get_many_in(self.category).await
+    }
+    async fn get_random(&self) -> Result<Image> {
+        self.get().await
+    }
+    fn max_batch(&self) -> usize {
+        self.batch
+    }
+}
+
+#[async_trait]
+impl CategorizedAgent for Counter {
+    type Category = Categories;
+
+    fn categories() -> Vec<Categories> {
+        Categories::variants()
+    }
+    fn category(&self) -> Categories {
+        self.category
+    }
+    fn set_category(&mut self, category: Categories) {
+        self.category = category;
+    }
+    async fn get_in(&self, category: Categories) -> Result<Image> {
+        let images = self.request(category, 1).await?;
+        Ok(images.into_vec().remove(0))
+    }
+    async fn get_many_in(&self, category: Categories) -> Result<Images> {
+        self.request(category, self.batch).await
+    }
+}
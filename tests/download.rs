@@ -0,0 +1,213 @@
+mod common;
+
+#[cfg(test)]
+mod test {
+    use crate::common::{MockServer, Response};
+    use anime_grubber::{
+        agents::waifu_pics::Waifu,
+        context::AgentContext,
+        download::{sniff::FileType, Downloader},
+        middleware::before_fn,
+        transport::{self, MemoryTransport, Request, StreamingResponse, Transport},
+        Error, Result,
+    };
+    use async_trait::async_trait;
+    use bytes::Bytes;
+    use futures_util::{stream, StreamExt};
+    use reqwest::{
+        header::{HeaderValue, CONTENT_TYPE},
+        StatusCode,
+    };
+
+    const GIF: &[u8] = b"GIF89a\x01\x00\x01\x00";
+
+    /// Sends the body in chunks of `size` bytes.
+    #[derive(Debug)]
+    struct Chunked {
+        body: Vec<u8>,
+        size: usize,
+    }
+
+    #[async_trait]
+    impl Transport for Chunked {
+        async fn send(&self, request: Request) -> Result<transport::Response> {
+            self.send_streaming(request).await?.collect().await
+        }
+
+        async fn send_streaming(&self, request: Request) -> Result<StreamingResponse> {
+            let chunks: Vec<Result<Bytes>> = self
+                .body
+                .chunks(self.size)
+                .map(|chunk| Ok(Bytes::copy_from_slice(chunk)))
+                .collect();
+            Ok(StreamingResponse {
+                status: StatusCode::OK,
+                url: request.url,
+                headers: Default::default(),
+                body: stream::iter(chunks).boxed(),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn downloads_through_agent_client() -> anyhow::Result<()> {
+        let server = MockServer::start(|_| {
+            Response::new(200)
+                .header("content-type", "image/gif")
+                .body(GIF)
+        })
+        .await;
+        let waifu = Waifu::builder().user_agent("grubber-test/1.0").build()?;
+        let file = waifu
+            .downloader()
+            .download(format!("{}/a.gif", server.url()))
+            .await?;
+        assert_eq!(file.bytes, GIF);
+        assert_eq!(file.content_type.as_deref(), Some("image/gif"));
+        assert_eq!(file.content_length, GIF.len() as u64);
+
+        let request = &server.requests()[0];
+        assert_eq!(request.target, "/a.gif");
+        assert_eq!(request.header("user-agent"), Some("grubber-test/1.0"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rejects_announced_size() -> anyhow::Result<()> {
+        let server = MockServer::start(|_| Response::new(200).body(vec![0; 1024])).await;
+        let downloader = Waifu::new(Default::default()).downloader().max_size(100);
+        let url = format!("{}/big.gif", server.url());
+        match downloader.stream(&url).await {
+            Err(Error::TooLarge { url: failed, limit }) => {
+                assert_eq!(failed, url);
+                assert_eq!(limit, 100);
+            }
+            other => panic!("unexpected {other:?}"),
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rejects_streamed_size() -> anyhow::Result<()> {
+        let context = AgentContext::with_transport(Chunked {
+            body: vec![0; 1000],
+            size: 300,
+        });
+        let downloader = Downloader::new(context).max_size(500);
+        let mut stream = downloader.stream("https://i.waifu.pics/big.gif").await?;
+        assert_eq!(stream.content_length(), None);
+        assert_eq!(stream.next().await.transpose()?.map(|c| c.len()), Some(300));
+        assert!(matches!(
+            stream.next().await,
+            Some(Err(Error::TooLarge { limit: 500, .. }))
+        ));
+        assert!(stream.next().await.is_none());
+
+        assert!(matches!(
+            downloader.download("https://i.waifu.pics/big.gif").await,
+            Err(Error::TooLarge { .. })
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn streams_chunks() -> anyhow::Result<()> {
+        let body: Vec<u8> = (0..=255).collect();
+        let context = AgentContext::with_transport(Chunked {
+            body: body.clone(),
+            size: 100,
+        });
+        let mut stream = Downloader::new(context)
+            .stream("https://i.waifu.pics/a.gif")
+            .await?;
+        let mut sizes = Vec::new();
+        let mut received = Vec::new();
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk?;
+            sizes.push(chunk.len());
+            received.extend_from_slice(&chunk);
+        }
+        assert_eq!(sizes, [100, 100, 56]);
+        assert_eq!(received, body);
+        assert_eq!(stream.read(), 256);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn maps_statuses_and_runs_middleware() -> anyhow::Result<()> {
+        let transport = MemoryTransport::new();
+        let context =
+            AgentContext::with_transport(transport.clone()).with_middleware(before_fn(|request| {
+                request
+                    .headers
+                    .insert("referer", HeaderValue::from_static("https://waifu.pics"));
+            }));
+        let result = Downloader::new(context)
+            .download("https://i.waifu.pics/missing.gif")
+            .await;
+        assert!(matches!(result, Err(Error::NotFound)));
+        assert_eq!(
+            transport.requests()[0].headers["referer"],
+            "https://waifu.pics"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn verifies_content_across_chunks() -> anyhow::Result<()> {
+        let body = [b"GIF89a".as_slice(), &[0; 100]].concat();
+        let context = AgentContext::with_transport(Chunked {
+            body: body.clone(),
+            size: 5,
+        });
+        let mut stream = Downloader::new(context)
+            .verify_content(true)
+            .stream("https://i.waifu.pics/a.gif")
+            .await?;
+        let mut sizes = Vec::new();
+        let mut received = Vec::new();
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk?;
+            sizes.push(chunk.len());
+            received.extend_from_slice(&chunk);
+        }
+        assert_eq!(sizes[..2], [15, 5]);
+        assert_eq!(received, body);
+        assert_eq!(stream.file_type(), Some(FileType::Gif));
+
+        let context = AgentContext::with_transport(Chunked {
+            body: GIF.to_vec(),
+            size: 3,
+        });
+        let file = Downloader::new(context)
+            .verify_content(true)
+            .download("https://i.waifu.pics/a.gif")
+            .await?;
+        assert_eq!(file.bytes, GIF);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rejects_error_page() -> anyhow::Result<()> {
+        let page = transport::Response::new(StatusCode::OK)
+            .header(CONTENT_TYPE, HeaderValue::from_static("text/html"))
+            .body("<html><body>Service unavailable</body></html>");
+        let transport = MemoryTransport::new().get("https://i.waifu.pics/a.gif", page);
+        let downloader = Downloader::new(AgentContext::with_transport(transport));
+
+        let result = downloader
+            .clone()
+            .verify_content(true)
+            .download("https://i.waifu.pics/a.gif")
+            .await;
+        assert!(
+            matches!(&result, Err(Error::ContentMismatch { url, .. }) if url == "https://i.waifu.pics/a.gif"),
+            "{result:?}"
+        );
+        assert!(downloader
+            .download("https://i.waifu.pics/a.gif")
+            .await
+            .is_ok());
+        Ok(())
+    }
+}
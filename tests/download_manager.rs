@@ -0,0 +1,277 @@
+mod common;
+
+#[cfg(test)]
+mod test {
+    use crate::common::fs::TempDir;
+    use anime_grubber::{
+        agents::waifu_pics::{Categories, Waifu, SFW},
+        context::AgentContext,
+        download::{
+            manager::{Collision, DownloadManager, Saved},
+            Downloader,
+        },
+        image::Image,
+        transport::{MemoryTransport, Response},
+    };
+    use futures_util::future::join_all;
+    use reqwest::StatusCode;
+
+    /// Answers every URL with its own path as the body.
+    fn echo() -> MemoryTransport {
+        MemoryTransport::from_fn(|request| {
+            Ok(Response::new(StatusCode::OK).body(request.url.clone()))
+        })
+    }
+
+    fn manager(dir: &TempDir, transport: &MemoryTransport) -> DownloadManager {
+        let context = AgentContext::with_transport(transport.clone());
+        DownloadManager::new(&dir.0, Downloader::new(context))
+    }
+
+    fn hug(name: &str) -> Image {
+        Image::new(format!("https://i.waifu.pics/{name}"), "waifu_pics", "sfw").with_aspect("hug")
+    }
+
+    #[tokio::test]
+    async fn saves_by_template() -> anyhow::Result<()> {
+        let dir = TempDir::new();
+        let transport = echo();
+        let saved = manager(&dir, &transport).save(&hug("abcd.gif")).await?;
+        let path = dir.0.join("waifu_pics/sfw/hug/abcd.gif");
+        assert_eq!(
+            saved,
+            Saved::Written {
+                path: path.clone(),
+                bytes: "https://i.waifu.pics/abcd.gif".len() as u64
+            }
+        );
+        assert_eq!(
+            std::fs::read_to_string(&path)?,
+            "https://i.waifu.pics/abcd.gif"
+        );
+        assert_eq!(dir.files(), [path]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn skips_present_files() -> anyhow::Result<()> {
+        let dir = TempDir::new();
+        let transport = echo();
+        let manager = manager(&dir, &transport);
+        manager.save(&hug("abcd.gif")).await?;
+        let saved = manager.save(&hug("abcd.gif")).await?;
+        assert!(matches!(saved, Saved::Skipped { .. }));
+        assert_eq!(transport.requests().len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn renames_on_collision() -> anyhow::Result<()> {
+        let dir = TempDir::new();
+        let transport = echo();
+        let manager = manager(&dir, &transport)
+            .template("{filename}")
+            .on_collision(Collision::Rename);
+        manager.save(&hug("abcd.gif")).await?;
+        let second = manager.save(&hug("abcd.gif")).await?;
+        let third = manager.save(&hug("abcd.gif")).await?;
+        assert_eq!(second.path(), dir.0.join("abcd-1.gif"));
+        assert_eq!(third.path(), dir.0.join("abcd-2.gif"));
+        assert_eq!(dir.files().len(), 3);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn concurrent_duplicates_get_own_files() -> anyhow::Result<()> {
+        let dir = TempDir::new();
+        let transport = echo();
+        let image = hug("abcd.gif");
+        let skip = manager(&dir, &transport).template("skip/{filename}");
+        let saved = join_all((0..8).map(|_| skip.save(&image))).await;
+        let written = saved
+            .iter()
+            .filter(|saved| matches!(saved, Ok(Saved::Written { .. })))
+            .count();
+        assert_eq!(written, 1);
+        assert!(saved.iter().all(Result::is_ok));
+
+        let rename = manager(&dir, &transport)
+            .template("rename/{filename}")
+            .on_collision(Collision::Rename);
+        let saved = join_all((0..8).map(|_| rename.save(&image))).await;
+        let mut paths = Vec::new();
+        for saved in saved {
+            let Saved::Written { path, .. } = saved? else {
+                panic!("expected written file");
+            };
+            paths.push(path);
+        }
+        paths.sort();
+        paths.dedup();
+        assert_eq!(paths.len(), 8);
+        assert_eq!(dir.files().len(), 9);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn keeps_file_created_during_download() -> anyhow::Result<()> {
+        for hard_links in [true, false] {
+            keeps_file_created_during_download_with(hard_links).await?;
+        }
+        Ok(())
+    }
+
+    async fn keeps_file_created_during_download_with(hard_links: bool) -> anyhow::Result<()> {
+        let dir = TempDir::new();
+        let target = dir.0.join("abcd.gif");
+        let transport = MemoryTransport::from_fn({
+            let target = target.clone();
+            move |request| {
+                std::fs::write(&target, "other").unwrap();
+                Ok(Response::new(StatusCode::OK).body(request.url.clone()))
+            }
+        });
+        let skip = manager(&dir, &transport)
+            .template("{filename}")
+            .hard_links(hard_links);
+        assert!(matches!(
+            skip.save(&hug("abcd.gif")).await?,
+            Saved::Skipped { .. }
+        ));
+        assert_eq!(std::fs::read_to_string(&target)?, "other");
+        assert_eq!(dir.files(), std::slice::from_ref(&target));
+
+        std::fs::remove_file(&target)?;
+        let rename = skip.on_collision(Collision::Rename);
+        let saved = rename.save(&hug("abcd.gif")).await?;
+        assert_eq!(saved.path(), dir.0.join("abcd-1.gif"));
+        assert_eq!(std::fs::read_to_string(&target)?, "other");
+        assert_eq!(
+            std::fs::read_to_string(saved.path())?,
+            "https://i.waifu.pics/abcd.gif"
+        );
+        assert_eq!(dir.files().len(), 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn saves_without_hard_links() -> anyhow::Result<()> {
+        let dir = TempDir::new();
+        let transport = echo();
+        let manager = manager(&dir, &transport)
+            .template("{filename}")
+            .hard_links(false)
+            .on_collision(Collision::Rename);
+        let image = hug("abcd.gif");
+        let saved = join_all((0..4).map(|_| manager.save(&image))).await;
+        for saved in saved {
+            let path = saved?.path().to_owned();
+            assert_eq!(
+                std::fs::read_to_string(path)?,
+                "https://i.waifu.pics/abcd.gif"
+            );
+        }
+        assert_eq!(dir.files().len(), 4);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn overwrites_on_collision() -> anyhow::Result<()> {
+        let dir = TempDir::new();
+        std::fs::write(dir.0.join("abcd.gif"), "old")?;
+        let transport = echo();
+        let manager = manager(&dir, &transport)
+            .template("{filename}")
+            .on_collision(Collision::Overwrite);
+        manager.save(&hug("abcd.gif")).await?;
+        assert_eq!(
+            std::fs::read_to_string(dir.0.join("abcd.gif"))?,
+            "https://i.waifu.pics/abcd.gif"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn failed_download_leaves_nothing() -> anyhow::Result<()> {
+        let dir = TempDir::new();
+        let transport = MemoryTransport::new();
+        let result = manager(&dir, &transport).save(&hug("gone.gif")).await;
+        assert!(result.is_err());
+        assert!(dir.files().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn values_stay_inside_root() {
+        let dir = TempDir::new();
+        let manager = manager(&dir, &echo()).template("{agent}/{kind}/{stem}.{ext}");
+        let image = Image::new("https://i.waifu.pics/..", "..", "nsfw").with_aspect("neko");
+        assert_eq!(manager.path_for(&image), dir.0.join("__/unknown/_."));
+        let image = Image::new("https://i.waifu.pics/a.png", "waifu_pics", "sfw");
+        assert_eq!(
+            manager.path_for(&image),
+            dir.0.join("waifu_pics/static/a.png")
+        );
+    }
+
+    #[test]
+    fn keeps_unknown_and_unclosed_placeholders() {
+        let dir = TempDir::new();
+        let manager = manager(&dir, &echo()).template("{aspect}-{size}/{stem}-{ext");
+        assert_eq!(
+            manager.path_for(&hug("abcd.gif")),
+            dir.0.join("hug-{size}/abcd-{ext")
+        );
+    }
+
+    #[tokio::test]
+    async fn names_files_without_file_name() -> anyhow::Result<()> {
+        let dir = TempDir::new();
+        let transport = echo();
+        let manager = manager(&dir, &transport);
+        let image = hug("");
+        let path = manager.path_for(&image);
+        assert_eq!(
+            path.parent(),
+            Some(dir.0.join("waifu_pics/sfw/hug").as_path())
+        );
+        assert!(path
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .starts_with("image-"));
+        assert_eq!(manager.path_for(&image), path);
+
+        let saved = manager.save(&image).await?;
+        assert!(matches!(saved, Saved::Written { .. }));
+        assert_eq!(dir.files(), [path]);
+        assert!(matches!(manager.save(&image).await?, Saved::Skipped { .. }));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn saves_agent_batch() -> anyhow::Result<()> {
+        let dir = TempDir::new();
+        let transport = echo().post(
+            "https://api.waifu.pics/many/sfw/pat",
+            Response::json(
+                r#"{"files":["https://i.waifu.pics/a.png","https://i.waifu.pics/b.png"]}"#,
+            ),
+        );
+        let waifu = Waifu::builder()
+            .categorie(Categories::SFW(SFW::Pat))
+            .transport(transport.clone())
+            .build()?;
+        let manager = DownloadManager::new(&dir.0, waifu.downloader());
+        let saved = manager.fetch_and_save(&waifu).await?;
+        assert_eq!(saved.len(), 2);
+        assert_eq!(
+            dir.files(),
+            [
+                dir.0.join("waifu_pics/sfw/pat/a.png"),
+                dir.0.join("waifu_pics/sfw/pat/b.png")
+            ]
+        );
+        Ok(())
+    }
+}
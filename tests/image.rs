@@ -0,0 +1,60 @@
+#[cfg(test)]
+mod test {
+    use anime_grubber::{
+        agent::ImageUrl,
+        image::{Dimensions, Image, MediaKind},
+    };
+
+    #[test]
+    fn media_kind_from_url() {
+        let gif = Image::new("https://i.waifu.pics/a.GIF", "waifu_pics", "sfw");
+        assert_eq!(gif.kind, MediaKind::Animated);
+        assert_eq!(gif.extension.as_deref(), Some("gif"));
+
+        let jpg = Image::new("https://i.waifu.pics/b.jpg", "waifu_pics", "sfw");
+        assert_eq!(jpg.kind, MediaKind::Static);
+
+        let video = Image::new("https://example.com/c.webm#t=1", "test", "test");
+        assert_eq!(video.kind, MediaKind::Video);
+        assert_eq!(video.file_name(), "c.webm");
+    }
+
+    #[test]
+    fn unknown_extension() {
+        let image = Image::new("https://example.com/file", "test", "test");
+        assert_eq!(image.kind, MediaKind::Unknown);
+        assert_eq!(image.extension, None);
+    }
+
+    #[test]
+    fn provider_fields() {
+        let image = Image::new("https://example.com/a.png", "test", "nsfw")
+            .with_aspect("neko")
+            .with_artist("someone")
+            .with_source("https://example.com/post/1")
+            .with_dimensions(640, 480);
+        assert_eq!(image.artist.as_deref(), Some("someone"));
+        assert_eq!(image.source.as_deref(), Some("https://example.com/post/1"));
+        assert_eq!(
+            image.dimensions,
+            Some(Dimensions {
+                width: 640,
+                height: 480
+            })
+        );
+        assert_eq!(image.category, "nsfw");
+        assert_eq!(image.aspect.as_deref(), Some("neko"));
+        assert_eq!(image.category_path(), "nsfw/neko");
+    }
+
+    #[test]
+    fn into_image_url() {
+        let image =
+            Image::new("https://i.waifu.pics/a.gif", "waifu_pics", "sfw").with_aspect("hug");
+        let borrowed: ImageUrl = (&image).into();
+        assert_eq!(borrowed, "https://i.waifu.pics/a.gif");
+        assert_eq!(image.to_string(), "https://i.waifu.pics/a.gif");
+        let owned: ImageUrl = image.into();
+        assert_eq!(owned, "https://i.waifu.pics/a.gif");
+    }
+}
@@ -0,0 +1,162 @@
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use anime_grubber::{
+        agent::Agent,
+        agents::waifu_pics::{Categories, Waifu, SFW},
+        context::AgentContext,
+        middleware::{after_fn, before_fn, Middleware},
+        transport::{MemoryTransport, Request, Response},
+        Error, Result,
+    };
+    use async_trait::async_trait;
+    use reqwest::header::{HeaderValue, AUTHORIZATION};
+
+    const HUG: &str = "https://api.waifu.pics/sfw/hug";
+
+    fn hug() -> Response {
+        Response::json(r#"{"url":"https://i.waifu.pics/hug.gif"}"#)
+    }
+
+    #[derive(Debug)]
+    struct Record {
+        name: &'static str,
+        log: Arc<Mutex<Vec<String>>>,
+        answer: bool,
+    }
+
+    #[async_trait]
+    impl Middleware for Record {
+        async fn before(&self, _request: &mut Request) -> Result<Option<Response>> {
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("before {}", self.name));
+            Ok(self.answer.then(hug))
+        }
+
+        async fn after(&self, _response: &mut Response) -> Result<()> {
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("after {}", self.name));
+            Ok(())
+        }
+    }
+
+    fn record(name: &'static str, log: &Arc<Mutex<Vec<String>>>, answer: bool) -> Record {
+        Record {
+            name,
+            log: log.clone(),
+            answer,
+        }
+    }
+
+    #[tokio::test]
+    async fn hooks_run_in_order() -> anyhow::Result<()> {
+        let log = Arc::default();
+        let transport = MemoryTransport::new().get(HUG, hug());
+        let waifu = Waifu::builder()
+            .categorie(Categories::SFW(SFW::Hug))
+            .transport(transport.clone())
+            .middleware(record("a", &log, false))
+            .middleware(record("b", &log, false))
+            .build()?;
+        waifu.get().await?;
+        assert_eq!(
+            *log.lock().unwrap(),
+            ["before a", "before b", "after b", "after a"]
+        );
+        assert_eq!(transport.requests().len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn short_circuit_skips_transport() -> anyhow::Result<()> {
+        let log = Arc::default();
+        let transport = MemoryTransport::new();
+        let waifu = Waifu::builder()
+            .categorie(Categories::SFW(SFW::Hug))
+            .transport(transport.clone())
+            .middleware(record("a", &log, false))
+            .middleware(record("b", &log, true))
+            .middleware(record("c", &log, false))
+            .build()?;
+        assert_eq!(waifu.get().await?.url, "https://i.waifu.pics/hug.gif");
+        assert_eq!(
+            *log.lock().unwrap(),
+            ["before a", "before b", "after b", "after a"]
+        );
+        assert!(transport.requests().is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rewrites_requests_and_responses() -> anyhow::Result<()> {
+        let transport = MemoryTransport::new().get("https://mirror.example/sfw/hug", hug());
+        let context = AgentContext::with_transport(transport.clone())
+            .with_middleware(before_fn(|request| {
+                request.url = request.url.replace("api.waifu.pics", "mirror.example");
+                request
+                    .headers
+                    .insert(AUTHORIZATION, HeaderValue::from_static("Bearer token"));
+            }))
+            .with_middleware(after_fn(|response| {
+                response.body = response.text().replace("hug.gif", "cached.gif").into();
+            }));
+        let waifu = Waifu::builder()
+            .categorie(Categories::SFW(SFW::Hug))
+            .context(&context)
+            .build()?;
+        assert_eq!(waifu.get().await?.url, "https://i.waifu.pics/cached.gif");
+        assert_eq!(
+            transport.requests()[0].headers[AUTHORIZATION],
+            "Bearer token"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn hook_errors_fail_request() -> anyhow::Result<()> {
+        #[derive(Debug)]
+        struct Deny;
+
+        #[async_trait]
+        impl Middleware for Deny {
+            async fn before(&self, _request: &mut Request) -> Result<Option<Response>> {
+                Err(Error::UnknownAgent("denied".to_owned()))
+            }
+        }
+
+        let transport = MemoryTransport::new().get(HUG, hug());
+        let waifu = Waifu::builder()
+            .transport(transport.clone())
+            .middleware(Deny)
+            .build()?;
+        assert!(matches!(waifu.get().await, Err(Error::UnknownAgent(_))));
+        assert!(transport.requests().is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn builder_does_not_change_shared_context() -> anyhow::Result<()> {
+        let log = Arc::default();
+        let transport = MemoryTransport::new().get(HUG, hug());
+        let context = AgentContext::with_transport(transport);
+        let plain = Waifu::builder()
+            .categorie(Categories::SFW(SFW::Hug))
+            .context(&context)
+            .build()?;
+        let recorded = Waifu::builder()
+            .categorie(Categories::SFW(SFW::Hug))
+            .context(&context)
+            .middleware(record("a", &log, false))
+            .build()?;
+        plain.get().await?;
+        assert!(log.lock().unwrap().is_empty());
+        recorded.get().await?;
+        assert_eq!(log.lock().unwrap().len(), 2);
+        Ok(())
+    }
+}
@@ -0,0 +1,130 @@
+mod common;
+
+#[cfg(test)]
+mod test {
+    use std::{
+        collections::HashSet,
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use crate::common::{agent::Counter, MockServer, Response};
+    use anime_grubber::{
+        agent::{Agent, CategorizedAgent},
+        agents::waifu_pics::{Categories, Waifu, NSFW, SFW},
+        layers::prefetch::Prefetched,
+    };
+
+    async fn settle() {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    #[tokio::test]
+    async fn serves_from_buffer_and_refills() -> anyhow::Result<()> {
+        let agent = Prefetched::new(Counter::new(5)).low_water(2);
+        agent.get().await?;
+        assert_eq!(agent.inner().calls(), 1);
+        assert_eq!(agent.buffered(Categories::default()), 4);
+
+        agent.get().await?;
+        agent.get().await?;
+        assert_eq!(agent.inner().calls(), 1);
+        agent.get().await?;
+        settle().await;
+        assert_eq!(agent.inner().calls(), 2);
+        assert_eq!(agent.buffered(Categories::default()), 6);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn never_repeats_urls() -> anyhow::Result<()> {
+        let agent = Prefetched::new(Counter::new(4)).low_water(1);
+        let mut seen = HashSet::new();
+        for _ in 0..20 {
+            assert!(seen.insert(agent.get().await?.url));
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn buffers_per_category() -> anyhow::Result<()> {
+        let agent = Prefetched::new(Counter::new(3));
+        let hug = agent.get_in(Categories::SFW(SFW::Hug)).await?;
+        let neko = agent.get_in(Categories::NSFW(NSFW::Neko)).await?;
+        assert_eq!(hug.category_path(), "sfw/hug");
+        assert_eq!(neko.category_path(), "nsfw/neko");
+        assert_eq!(agent.buffered(Categories::SFW(SFW::Hug)), 2);
+        assert_eq!(agent.buffered(Categories::NSFW(NSFW::Neko)), 2);
+
+        let mut clone = agent.clone();
+        clone.set_category(Categories::SFW(SFW::Hug));
+        clone.get().await?;
+        assert_eq!(agent.buffered(Categories::SFW(SFW::Hug)), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn deduplicates_batch() -> anyhow::Result<()> {
+        let server = MockServer::start(|_| {
+            Response::json(
+                r#"{"files":["https://i.waifu.pics/a.gif","https://i.waifu.pics/a.gif","https://i.waifu.pics/b.gif"]}"#,
+            )
+        })
+        .await;
+        let waifu = Waifu::new(Categories::SFW(SFW::Hug)).with_base_url(server.url());
+        let agent = Prefetched::new(waifu).low_water(0);
+
+        agent.prefetch(Categories::SFW(SFW::Hug)).await?;
+        assert_eq!(agent.buffered(Categories::SFW(SFW::Hug)), 2);
+        assert_eq!(agent.get().await?.url, "https://i.waifu.pics/a.gif");
+        assert_eq!(agent.get().await?.url, "https://i.waifu.pics/b.gif");
+        assert_eq!(server.requests().len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn batch_uses_inner_batching() -> anyhow::Result<()> {
+        let calls = AtomicUsize::new(0);
+        let server = MockServer::start(move |_| {
+            let n = calls.fetch_add(1, Ordering::SeqCst);
+            Response::json(format!(
+                r#"{{"files":["https://i.waifu.pics/{}.gif","https://i.waifu.pics/{}.gif"]}}"#,
+                2 * n,
+                2 * n + 1
+            ))
+        })
+        .await;
+        let waifu = Waifu::new(Categories::SFW(SFW::Hug)).with_base_url(server.url());
+        let agent = Prefetched::new(waifu);
+        assert_eq!(agent.get_batch(4).await?.len(), 4);
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[1].body_str().contains("0.gif"));
+        assert_eq!(agent.buffered(Categories::SFW(SFW::Hug)), 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn other_category_batch_uses_get_many() -> anyhow::Result<()> {
+        let mut agent = Prefetched::new(Counter::new(3));
+        agent.set_category(Categories::NSFW(NSFW::Neko));
+        let neko = agent.get_batch(5).await?;
+        assert_eq!(neko.len(), 5);
+        assert!(neko
+            .iter()
+            .all(|image| image.category_path() == Categories::NSFW(NSFW::Neko).path()));
+        assert_eq!(agent.inner().calls(), 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn concurrent_gets_share_refill() -> anyhow::Result<()> {
+        let agent = Prefetched::new(Counter::new(10).with_delay(Duration::from_millis(20)));
+        let (a, b, c) = tokio::join!(agent.get(), agent.get(), agent.get());
+        let urls: HashSet<String> = [a?, b?, c?].into_iter().map(|image| image.url).collect();
+        assert_eq!(urls.len(), 3);
+        assert_eq!(agent.inner().calls(), 1);
+        Ok(())
+    }
+}
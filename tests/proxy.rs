@@ -0,0 +1,91 @@
+mod common;
+
+#[cfg(test)]
+mod test {
+    use crate::common::{MockServer, Response};
+    use anime_grubber::{
+        agent::Agent,
+        agents::waifu_pics::{Categories, Waifu, SFW},
+        proxy::ProxyConfig,
+        Error,
+    };
+
+    fn image() -> Response {
+        Response::json(r#"{"url":"https://i.waifu.pics/a.gif"}"#)
+    }
+
+    #[tokio::test]
+    async fn routes_requests_through_proxy() -> anyhow::Result<()> {
+        let proxy = MockServer::start(|_| image()).await;
+        let waifu = Waifu::builder()
+            .categorie(Categories::SFW(SFW::Hug))
+            .base_url("http://api.waifu.test")
+            .proxy(ProxyConfig::new(proxy.url()).basic_auth("user", "secret"))
+            .build()?;
+        assert_eq!(waifu.get().await?.url, "https://i.waifu.pics/a.gif");
+
+        let requests = proxy.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].target, "http://api.waifu.test/sfw/hug");
+        assert_eq!(
+            requests[0].header("proxy-authorization"),
+            Some("Basic dXNlcjpzZWNyZXQ=")
+        );
+        Ok(())
+    }
+
+    #[cfg(any(feature = "native-tls", feature = "rustls-tls"))]
+    #[tokio::test]
+    async fn tunnels_https_through_proxy() -> anyhow::Result<()> {
+        let proxy = MockServer::start(|_| Response::new(502)).await;
+        let context =
+            anime_grubber::context::AgentContext::with_proxy(&ProxyConfig::new(proxy.url()))?;
+        let waifu = Waifu::builder().context(&context).build()?;
+        assert!(matches!(waifu.get().await, Err(Error::Reqwest(_))));
+
+        let requests = proxy.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].method, "CONNECT");
+        assert_eq!(requests[0].target, "api.waifu.pics:443");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn bypasses_no_proxy_hosts() -> anyhow::Result<()> {
+        let proxy = MockServer::start(|_| Response::new(502)).await;
+        let server = MockServer::start(|_| image()).await;
+        let waifu = Waifu::builder()
+            .base_url(server.url())
+            .proxy(ProxyConfig::new(proxy.url()).no_proxy(["127.0.0.1"]))
+            .build()?;
+        waifu.get().await?;
+        assert!(proxy.requests().is_empty());
+        assert_eq!(server.requests().len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_invalid_url() {
+        let result = Waifu::builder()
+            .proxy(ProxyConfig::new("not a url"))
+            .build();
+        assert!(matches!(result, Err(Error::Client(_))));
+    }
+
+    #[test]
+    fn debug_hides_password() {
+        let proxy = ProxyConfig::new("http://proxy.example:3128").basic_auth("user", "secret");
+        let debug = format!("{proxy:?}");
+        assert!(!debug.contains("secret"), "{debug}");
+        assert!(debug.contains(r#"Some(("user", "<redacted>"))"#), "{debug}");
+    }
+
+    #[cfg(feature = "socks")]
+    #[test]
+    fn accepts_socks5() -> anyhow::Result<()> {
+        Waifu::builder()
+            .proxy(ProxyConfig::new("socks5h://127.0.0.1:9050"))
+            .build()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,129 @@
+mod common;
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        time::Duration,
+    };
+
+    use crate::common::agent::Counter;
+    use anime_grubber::{
+        agent::{Agent, CategorizedAgent},
+        agents::waifu_pics::{Categories, Waifu, SFW},
+        layers::rate_limit::{RateLimitMiddleware, RateLimited, RateLimiter, TokenBucket},
+        transport::{MemoryTransport, Response},
+        Error,
+    };
+    use tokio::time::Instant;
+
+    /// Asserts that no two of `started` are closer than `gap`.
+    fn assert_spaced(started: &[Instant], gap: Duration) {
+        for pair in started.windows(2) {
+            let elapsed = pair[1] - pair[0];
+            assert!(elapsed >= gap, "{elapsed:?}");
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn burst_then_rate() -> anyhow::Result<()> {
+        let agent = RateLimited::new(Counter::new(1), TokenBucket::new(2.0, 3)?);
+        let start = Instant::now();
+        for _ in 0..3 {
+            agent.get().await?;
+        }
+        assert!(start.elapsed() < Duration::from_millis(1));
+
+        for _ in 0..4 {
+            agent.get().await?;
+        }
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(2000), "{elapsed:?}");
+        assert!(elapsed < Duration::from_millis(2100), "{elapsed:?}");
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn clones_and_categories_share_bucket() -> anyhow::Result<()> {
+        let agent = RateLimited::new(Counter::new(1), TokenBucket::new(1.0, 1)?);
+        let clone = agent.clone();
+        let start = Instant::now();
+        agent.get_in(Categories::SFW(SFW::Hug)).await?;
+        clone.get_in(Categories::SFW(SFW::Pat)).await?;
+        assert!(start.elapsed() >= Duration::from_secs(1));
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn fail_fast() -> anyhow::Result<()> {
+        let limiter: Arc<dyn RateLimiter> = Arc::new(TokenBucket::new(1.0, 1)?.fail_fast());
+        let first = RateLimited::with_shared(Counter::new(1), limiter.clone());
+        let second = RateLimited::with_shared(Counter::new(1), limiter);
+
+        first.get().await?;
+        match second.get().await {
+            Err(Error::RateLimit {
+                retry_after: Some(retry_after),
+            }) => assert!(retry_after <= Duration::from_secs(1)),
+            other => panic!("unexpected {other:?}"),
+        }
+        assert_eq!(second.inner().calls(), 0);
+
+        tokio::time::advance(Duration::from_secs(1)).await;
+        second.get().await?;
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn batch_spaces_every_request() -> anyhow::Result<()> {
+        let agent = RateLimited::new(Counter::new(2), TokenBucket::new(1.0, 1)?);
+        assert_eq!(agent.get_batch(6).await?.len(), 6);
+        assert_eq!(agent.inner().calls(), 3);
+        assert_spaced(&agent.inner().started(), Duration::from_secs(1));
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn middleware_spaces_inner_batching() -> anyhow::Result<()> {
+        let calls = AtomicUsize::new(0);
+        let started = Arc::new(Mutex::new(Vec::new()));
+        let transport = MemoryTransport::from_fn({
+            let started = started.clone();
+            move |_| {
+                started.lock().unwrap().push(Instant::now());
+                let n = calls.fetch_add(1, Ordering::SeqCst);
+                Ok(Response::json(format!(
+                    r#"{{"files":["https://i.waifu.pics/{}.gif","https://i.waifu.pics/{}.gif"]}}"#,
+                    2 * n,
+                    2 * n + 1
+                )))
+            }
+        });
+        let waifu = Waifu::builder()
+            .categorie(Categories::SFW(SFW::Hug))
+            .transport(transport.clone())
+            .middleware(RateLimitMiddleware::new(TokenBucket::new(1.0, 1)?))
+            .build()?;
+        assert_eq!(waifu.get_batch(6).await?.len(), 6);
+
+        let requests = transport.requests();
+        assert_eq!(requests.len(), 3);
+        let body = String::from_utf8(requests[1].body.clone().unwrap_or_default())?;
+        assert!(body.contains("0.gif"));
+        assert_spaced(&started.lock().unwrap(), Duration::from_secs(1));
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_invalid_rate() {
+        for rate in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert!(matches!(
+                TokenBucket::new(rate, 1),
+                Err(Error::InvalidConfig(_))
+            ));
+        }
+    }
+}
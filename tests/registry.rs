@@ -0,0 +1,119 @@
+#[cfg(test)]
+mod test {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use anime_grubber::{
+        agent::{Agent, Images},
+        agents::waifu_pics::{Categories, SFW},
+        image::Image,
+        registry::{AgentFactory, AgentRegistry, BoxedAgent},
+        Error, Result,
+    };
+    use async_trait::async_trait;
+
+    struct Fixed(&'static str);
+
+    #[async_trait]
+    impl Agent for Fixed {
+        async fn get(&self) -> Result<Image> {
+            Ok(Image::new(self.0, "fixed", "test"))
+        }
+        async fn get_many(&self) -> Result<Images> {
+            Ok([self.get().await?].into())
+        }
+        async fn get_random(&self) -> Result<Image> {
+            self.get().await
+        }
+        fn max_batch(&self) -> usize {
+            1
+        }
+    }
+
+    #[derive(Default)]
+    struct FixedFactory {
+        built: Arc<AtomicUsize>,
+    }
+
+    impl AgentFactory for FixedFactory {
+        fn categories(&self) -> Vec<String> {
+            vec!["a".to_owned(), "b".to_owned()]
+        }
+        fn build(&self, category: Option<&str>) -> Result<BoxedAgent> {
+            self.built.fetch_add(1, Ordering::SeqCst);
+            match category {
+                None | Some("a") => Ok(Box::new(Fixed("https://example.com/a.png"))),
+                Some("b") => Ok(Box::new(Fixed("https://example.com/b.png"))),
+                Some(other) => Err(Error::UnknownCategory(other.to_owned())),
+            }
+        }
+    }
+
+    #[test]
+    fn default_knows_waifu_pics() {
+        let registry = AgentRegistry::default();
+        assert_eq!(registry.names().collect::<Vec<_>>(), ["waifu_pics"]);
+        let categories = registry.categories("waifu_pics").unwrap();
+        assert!(categories.contains(&"sfw/hug".to_owned()));
+        assert!(categories.contains(&"nsfw/neko".to_owned()));
+        assert_eq!(categories.len(), Categories::variants().len());
+        assert!(registry.build("waifu_pics:sfw/hug").is_ok());
+        assert!(registry.build("waifu_pics").is_ok());
+    }
+
+    #[test]
+    fn unknown_specs() {
+        let registry = AgentRegistry::default();
+        assert!(matches!(
+            registry.build("nekos:sfw/hug"),
+            Err(Error::UnknownAgent(name)) if name == "nekos"
+        ));
+        assert!(matches!(
+            registry.build("waifu_pics:sfw/nope"),
+            Err(Error::UnknownCategory(category)) if category == "sfw/nope"
+        ));
+    }
+
+    #[tokio::test]
+    async fn custom_factory() -> anyhow::Result<()> {
+        let factory = FixedFactory::default();
+        let built = factory.built.clone();
+        let mut registry = AgentRegistry::empty();
+        registry.register("fixed", factory);
+
+        let agent = registry.get_or_build("fixed:b")?;
+        assert_eq!(agent.get().await?.url, "https://example.com/b.png");
+        registry.get_or_build("fixed:b")?;
+        assert_eq!(built.load(Ordering::SeqCst), 1);
+
+        let boxed = registry.build("fixed")?;
+        assert_eq!(boxed.get_many().await?.len(), 1);
+        assert_eq!(boxed.max_batch(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn stored_agents() {
+        let mut registry = AgentRegistry::empty();
+        assert!(registry
+            .insert("main", Box::new(Fixed("https://example.com/a.png")))
+            .is_none());
+        assert!(registry.get("main").is_some());
+        assert!(registry.remove("main").is_some());
+        assert!(registry.get("main").is_none());
+    }
+
+    #[test]
+    fn categories_round_trip() {
+        for categorie in Categories::variants() {
+            assert_eq!(categorie.path().parse::<Categories>().unwrap(), categorie);
+        }
+        assert_eq!(
+            "SFW/Hug".parse::<Categories>().unwrap(),
+            Categories::SFW(SFW::Hug)
+        );
+        assert_eq!("sfw".parse::<Categories>().unwrap(), Categories::default());
+    }
+}
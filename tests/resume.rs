@@ -0,0 +1,319 @@
+mod common;
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use crate::common::{fs::TempDir, MockServer, Request, Response};
+    use anime_grubber::{
+        context::AgentContext,
+        download::{
+            manager::{DownloadManager, Saved},
+            Downloader,
+        },
+        image::Image,
+        layers::retry::{Backoff, Jitter, RetryPolicy},
+    };
+
+    const DROP_AFTER: usize = 100;
+
+    /// Content of each version, every version is 200 bytes longer than the one before.
+    fn body(version: u8) -> Vec<u8> {
+        (0..1000 + 200 * u32::from(version))
+            .map(|n| (n % 251) as u8 ^ version)
+            .collect()
+    }
+
+    #[derive(Clone, Copy)]
+    enum Ranges {
+        Honour,
+        /// Honours ranges without looking at `If-Range`.
+        Blind,
+        Ignore,
+        Reject,
+    }
+
+    #[derive(Clone, Copy)]
+    enum Validator {
+        ETag,
+        LastModified,
+        None,
+    }
+
+    impl Validator {
+        fn of(self, version: u8) -> Option<(&'static str, String)> {
+            match self {
+                Validator::ETag => Some(("etag", format!("\"v{version}\""))),
+                Validator::LastModified => Some((
+                    "last-modified",
+                    format!("Tue, 0{} Jan 2030 00:00:00 GMT", version + 1),
+                )),
+                Validator::None => None,
+            }
+        }
+    }
+
+    /// Serves a file whose first response drops the connection after [DROP_AFTER] bytes.
+    ///
+    /// From the `change_after`-th request on, a new version with another `ETag` is served.
+    async fn server(ranges: Ranges, change_after: usize) -> MockServer {
+        server_with(ranges, Validator::ETag, change_after).await
+    }
+
+    /// [server] identifying versions by `validator`.
+    async fn server_with(ranges: Ranges, validator: Validator, change_after: usize) -> MockServer {
+        let requests = Arc::new(AtomicUsize::new(0));
+        MockServer::start(move |request: &Request| {
+            let n = requests.fetch_add(1, Ordering::SeqCst);
+            let version = u8::from(n >= change_after);
+            let validator = validator.of(version);
+            let body = body(version);
+            let range = request
+                .header("range")
+                .and_then(|range| range.strip_prefix("bytes="))
+                .and_then(|range| range.trim_end_matches('-').parse::<usize>().ok())
+                .filter(|_| {
+                    matches!(ranges, Ranges::Blind)
+                        || request.header("if-range").is_none_or(|value| {
+                            validator
+                                .as_ref()
+                                .is_some_and(|(_, current)| value == current)
+                        })
+                });
+            let response = match (range, ranges) {
+                (Some(_), Ranges::Reject) => {
+                    return Response::new(416)
+                        .header("content-range", &format!("bytes */{}", body.len()))
+                }
+                (Some(start), Ranges::Honour | Ranges::Blind) => Response::new(206)
+                    .header(
+                        "content-range",
+                        &format!("bytes {start}-{}/{}", body.len() - 1, body.len()),
+                    )
+                    .body(&body[start..]),
+                _ => Response::new(200).body(body),
+            };
+            let response = match &validator {
+                Some((name, value)) => response.header(name, value),
+                None => response,
+            };
+            match n {
+                0 => response.drop_after(DROP_AFTER),
+                _ => response,
+            }
+        })
+        .await
+    }
+
+    fn manager(dir: &TempDir, server: &MockServer) -> (DownloadManager, Image) {
+        let downloader = Downloader::new(AgentContext::try_default().unwrap());
+        let image = Image::new(format!("{}/abcd.png", server.url()), "waifu_pics", "sfw")
+            .with_aspect("hug");
+        let manager = DownloadManager::new(&dir.0, downloader)
+            .template("{filename}")
+            .resume(true);
+        (manager, image)
+    }
+
+    fn quick_retry() -> RetryPolicy {
+        RetryPolicy::default()
+            .backoff(Backoff::Constant(Duration::ZERO))
+            .jitter(Jitter::None)
+    }
+
+    #[tokio::test]
+    async fn keeps_partial_file_and_resumes() -> anyhow::Result<()> {
+        let dir = TempDir::new();
+        let server = server(Ranges::Honour, usize::MAX).await;
+        let (manager, image) = manager(&dir, &server);
+
+        let error = manager.save(&image).await.unwrap_err();
+        assert!(RetryPolicy::is_transient(&error), "{error:?}");
+        let part = dir.0.join(".abcd.png.part");
+        let kept = std::fs::metadata(&part)?.len();
+        assert!(kept > 0 && kept <= DROP_AFTER as u64);
+        assert_eq!(
+            std::fs::read_to_string(dir.0.join(".abcd.png.part.meta"))?,
+            "etag: \"v0\"\nlength: 1000\n"
+        );
+
+        let saved = manager.save(&image).await?;
+        let path = dir.0.join("abcd.png");
+        assert_eq!(
+            saved,
+            Saved::Written {
+                path: path.clone(),
+                bytes: 1000
+            }
+        );
+        assert_eq!(std::fs::read(&path)?, body(0));
+        assert_eq!(dir.files(), [path]);
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(
+            requests[1].header("range"),
+            Some(&*format!("bytes={kept}-"))
+        );
+        assert_eq!(requests[1].header("if-range"), Some("\"v0\""));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn retry_policy_continues_download() -> anyhow::Result<()> {
+        let dir = TempDir::new();
+        let server = server(Ranges::Honour, usize::MAX).await;
+        let (manager, image) = manager(&dir, &server);
+        let manager = manager.retry_policy(quick_retry());
+
+        manager.save(&image).await?;
+        assert_eq!(std::fs::read(dir.0.join("abcd.png"))?, body(0));
+        assert_eq!(server.requests().len(), 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn restarts_when_range_is_ignored() -> anyhow::Result<()> {
+        let dir = TempDir::new();
+        let server = server(Ranges::Ignore, usize::MAX).await;
+        let (manager, image) = manager(&dir, &server);
+        let manager = manager.retry_policy(quick_retry());
+
+        manager.save(&image).await?;
+        assert_eq!(std::fs::read(dir.0.join("abcd.png"))?, body(0));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn restarts_when_file_changed() -> anyhow::Result<()> {
+        let dir = TempDir::new();
+        let server = server(Ranges::Honour, 1).await;
+        let (manager, image) = manager(&dir, &server);
+        let manager = manager.retry_policy(quick_retry());
+
+        manager.save(&image).await?;
+        assert_eq!(std::fs::read(dir.0.join("abcd.png"))?, body(1));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn resumes_with_last_modified() -> anyhow::Result<()> {
+        let dir = TempDir::new();
+        let server = server_with(Ranges::Honour, Validator::LastModified, usize::MAX).await;
+        let (manager, image) = manager(&dir, &server);
+        let manager = manager.retry_policy(quick_retry());
+
+        manager.save(&image).await?;
+        assert_eq!(std::fs::read(dir.0.join("abcd.png"))?, body(0));
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[1].header("range").is_some());
+        assert_eq!(
+            requests[1].header("if-range"),
+            Some("Tue, 01 Jan 2030 00:00:00 GMT")
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn restarts_when_file_without_etag_changed() -> anyhow::Result<()> {
+        let dir = TempDir::new();
+        let server = server_with(Ranges::Honour, Validator::LastModified, 1).await;
+        let (manager, image) = manager(&dir, &server);
+        let manager = manager.retry_policy(quick_retry());
+
+        manager.save(&image).await?;
+        assert_eq!(std::fs::read(dir.0.join("abcd.png"))?, body(1));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn restarts_without_validator() -> anyhow::Result<()> {
+        let dir = TempDir::new();
+        let server = server_with(Ranges::Honour, Validator::None, 1).await;
+        let (manager, image) = manager(&dir, &server);
+        let manager = manager.retry_policy(quick_retry());
+
+        manager.save(&image).await?;
+        assert_eq!(std::fs::read(dir.0.join("abcd.png"))?, body(1));
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[1].header("range").is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn restarts_when_length_changed() -> anyhow::Result<()> {
+        let dir = TempDir::new();
+        let server = server(Ranges::Blind, 1).await;
+        let (manager, image) = manager(&dir, &server);
+        let manager = manager.retry_policy(quick_retry());
+
+        manager.save(&image).await?;
+        assert_eq!(std::fs::read(dir.0.join("abcd.png"))?, body(1));
+        let requests = server.requests();
+        assert_eq!(requests.len(), 3);
+        assert!(requests[1].header("range").is_some());
+        assert!(requests[2].header("range").is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn finishes_complete_partial_file() -> anyhow::Result<()> {
+        let dir = TempDir::new();
+        let server = server(Ranges::Reject, usize::MAX).await;
+        let (manager, image) = manager(&dir, &server);
+        std::fs::write(dir.0.join(".abcd.png.part"), body(0))?;
+        std::fs::write(
+            dir.0.join(".abcd.png.part.meta"),
+            "etag: \"v0\"\nlength: 1000\n",
+        )?;
+
+        let saved = manager.save(&image).await?;
+        let path = dir.0.join("abcd.png");
+        assert_eq!(
+            saved,
+            Saved::Written {
+                path: path.clone(),
+                bytes: 1000
+            }
+        );
+        assert_eq!(std::fs::read(&path)?, body(0));
+        assert_eq!(dir.files(), [path]);
+        assert_eq!(server.requests().len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn restarts_when_range_is_rejected() -> anyhow::Result<()> {
+        let dir = TempDir::new();
+        let server = server(Ranges::Reject, usize::MAX).await;
+        let (manager, image) = manager(&dir, &server);
+
+        assert!(manager.save(&image).await.is_err());
+        manager.save(&image).await?;
+        assert_eq!(std::fs::read(dir.0.join("abcd.png"))?, body(0));
+        let requests = server.requests();
+        assert_eq!(requests.len(), 3);
+        assert!(requests[1].header("range").is_some());
+        assert!(requests[2].header("range").is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn removes_temporary_file_without_resume() -> anyhow::Result<()> {
+        let dir = TempDir::new();
+        let server = server(Ranges::Honour, usize::MAX).await;
+        let (manager, image) = manager(&dir, &server);
+
+        assert!(manager.resume(false).save(&image).await.is_err());
+        assert!(dir.files().is_empty());
+        Ok(())
+    }
+}
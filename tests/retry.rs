@@ -0,0 +1,186 @@
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Mutex,
+        },
+        time::Duration,
+    };
+
+    use anime_grubber::{
+        agent::{Agent, Images},
+        image::Image,
+        layers::retry::{Backoff, Jitter, Retry, RetryPolicy},
+        Error, Result,
+    };
+    use async_trait::async_trait;
+    use reqwest::StatusCode;
+    use tokio::time::Instant;
+
+    /// Fails with errors from `errors` until they run out.
+    #[derive(Default)]
+    struct Flaky {
+        errors: Mutex<Vec<Error>>,
+        calls: AtomicUsize,
+    }
+
+    impl Flaky {
+        fn new(errors: impl IntoIterator<Item = Error>) -> Self {
+            let mut errors: Vec<Error> = errors.into_iter().collect();
+            errors.reverse();
+            Self {
+                errors: Mutex::new(errors),
+                calls: AtomicUsize::new(0),
+            }
+        }
+
+        fn calls(&self) -> usize {
+            self.calls.load(Ordering::SeqCst)
+        }
+    }
+
+    #[async_trait]
+    impl Agent for Flaky {
+        async fn get(&self) -> Result<Image> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            match self.errors.lock().unwrap().pop() {
+                Some(error) => Err(error),
+                None => Ok(Image::new("https://example.com/a.png", "flaky", "test")),
+            }
+        }
+        async fn get_many(&self) -> Result<Images> {
+            Ok([self.get().await?].into())
+        }
+        async fn get_random(&self) -> Result<Image> {
+            self.get().await
+        }
+        fn max_batch(&self) -> usize {
+            1
+        }
+    }
+
+    fn unavailable() -> Error {
+        Error::RequestFailed {
+            status: StatusCode::SERVICE_UNAVAILABLE,
+            url: "https://example.com".to_owned(),
+            body: String::new(),
+        }
+    }
+
+    fn fast() -> RetryPolicy {
+        RetryPolicy::default()
+            .backoff(Backoff::Constant(Duration::from_millis(10)))
+            .jitter(Jitter::None)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retries_transient_errors() -> anyhow::Result<()> {
+        let agent = Retry::new(Flaky::new([unavailable(), unavailable()]), fast());
+        agent.get().await?;
+        assert_eq!(agent.inner().calls(), 3);
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn gives_up_after_max_attempts() {
+        let agent = Retry::new(
+            Flaky::new([unavailable(), unavailable(), unavailable()]),
+            fast().max_attempts(2),
+        );
+        assert!(matches!(
+            agent.get_many().await,
+            Err(Error::RequestFailed { .. })
+        ));
+        assert_eq!(agent.inner().calls(), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn permanent_errors_are_not_retried() {
+        let agent = Retry::new(Flaky::new([Error::NotFound]), fast());
+        assert!(matches!(agent.get().await, Err(Error::NotFound)));
+        assert_eq!(agent.inner().calls(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn custom_classifier() -> anyhow::Result<()> {
+        let policy = fast().retry_on(|error| matches!(error, Error::NotFound));
+        let agent = Retry::new(Flaky::new([Error::NotFound]), policy);
+        agent.get().await?;
+        assert_eq!(agent.inner().calls(), 2);
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn honours_retry_after() -> anyhow::Result<()> {
+        let rate_limit = Error::RateLimit {
+            retry_after: Some(Duration::from_secs(5)),
+        };
+        let agent = Retry::new(Flaky::new([rate_limit]), fast());
+        let start = Instant::now();
+        agent.get().await?;
+        assert!(start.elapsed() >= Duration::from_secs(5));
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn caps_retry_after() -> anyhow::Result<()> {
+        let rate_limit = Error::RateLimit {
+            retry_after: Some(Duration::from_secs(999_999_999)),
+        };
+        let policy = fast().max_retry_after(Duration::from_secs(2));
+        assert_eq!(policy.delay(1, &rate_limit), Duration::from_secs(2));
+        let agent = Retry::new(Flaky::new([rate_limit]), policy);
+        let start = Instant::now();
+        agent.get().await?;
+        assert!(start.elapsed() < Duration::from_secs(3));
+        Ok(())
+    }
+
+    #[test]
+    fn exponential_backoff() {
+        let backoff = Backoff::Exponential {
+            initial: Duration::from_millis(100),
+            multiplier: 2.0,
+            max: Duration::from_millis(500),
+        };
+        let delays: Vec<u128> = (1..=5)
+            .map(|retry| backoff.delay(retry).as_millis())
+            .collect();
+        assert_eq!(delays, [100, 200, 400, 500, 500]);
+    }
+
+    #[test]
+    fn backoff_never_overflows() {
+        let max = Duration::from_secs(10);
+        let backoff = Backoff::exponential(Duration::from_millis(200), 2.0, max).unwrap();
+        assert_eq!(backoff.delay(100), max);
+        assert_eq!(backoff.delay(u32::MAX), max);
+        for multiplier in [-2.0, f64::NAN, f64::INFINITY] {
+            let backoff = Backoff::Exponential {
+                initial: Duration::from_millis(200),
+                multiplier,
+                max,
+            };
+            for retry in 1..=4 {
+                assert!(backoff.delay(retry) <= max);
+            }
+            assert!(matches!(
+                Backoff::exponential(Duration::from_millis(200), multiplier, max),
+                Err(Error::InvalidConfig(_))
+            ));
+        }
+        assert!(Backoff::exponential(Duration::from_millis(200), 0.5, max).is_err());
+    }
+
+    #[test]
+    fn jitter_bounds() {
+        let delay = Duration::from_millis(100);
+        for _ in 0..100 {
+            assert!(Jitter::Full.apply(delay) <= delay);
+            let equal = Jitter::Equal.apply(delay);
+            assert!(equal >= delay / 2 && equal <= delay);
+        }
+        assert_eq!(Jitter::None.apply(delay), delay);
+    }
+}
@@ -0,0 +1,117 @@
+#[cfg(test)]
+mod test {
+    use anime_grubber::{
+        download::sniff::{verify, FileType},
+        image::MediaKind,
+        Error,
+    };
+
+    const HTML: &[u8] = b"<!DOCTYPE html><html>";
+
+    #[test]
+    fn sniffs_magic_bytes() {
+        let files: [(&[u8], FileType); 7] = [
+            (b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR", FileType::Png),
+            (b"\xff\xd8\xff\xe0\0\x10JFIF\0", FileType::Jpeg),
+            (b"GIF87a\x01\x00\x01\x00", FileType::Gif),
+            (b"GIF89a\x01\x00\x01\x00", FileType::Gif),
+            (b"RIFF\x24\0\0\0WEBPVP8 ", FileType::WebP),
+            (b"\0\0\0\x20ftypisom\0\0\x02\0", FileType::Mp4),
+            (b"\x1a\x45\xdf\xa3\x9f\x42\x86\x81", FileType::WebM),
+        ];
+        for (head, file_type) in files {
+            assert_eq!(FileType::sniff(head), Some(file_type));
+        }
+        assert_eq!(FileType::sniff(HTML), None);
+        assert_eq!(FileType::sniff(b"RIFF\x24\0\0\0WAVEfmt "), None);
+        assert_eq!(FileType::sniff(b"GIF"), None);
+        for mp4 in [
+            b"\0\0\0\x18ftypmp42\0\0\0\0",
+            b"\0\0\0\x1cftypM4V \0\0\0\x01",
+            b"\0\0\0\x1cftypiso3\0\0\0\x01",
+            b"\0\0\0\x1cftypMSNV\0\0\0\x01",
+            b"\0\0\0\x1cftyp3gp5\0\0\0\x01",
+        ] {
+            assert_eq!(FileType::sniff(mp4), Some(FileType::Mp4));
+        }
+        for other in [
+            b"\0\0\0\x18ftypheic\0\0\0\0",
+            b"\0\0\0\x18ftypmif1\0\0\0\0",
+            b"\0\0\0\x1cftypavif\0\0\0\0",
+            b"\0\0\0\x14ftypqt  \0\0\x02\0",
+        ] {
+            assert_eq!(FileType::sniff(other), None);
+        }
+        assert_eq!(FileType::sniff(b"\0\0\0\x20ftyp"), None);
+        assert_eq!(FileType::sniff(b""), None);
+    }
+
+    #[test]
+    fn maps_extensions_and_mime_types() {
+        assert_eq!(FileType::from_extension("JPG"), Some(FileType::Jpeg));
+        assert_eq!(FileType::from_extension("bmp"), None);
+        assert_eq!(
+            FileType::from_mime("Image/WebP; q=0.9"),
+            Some(FileType::WebP)
+        );
+        assert_eq!(FileType::from_mime("application/octet-stream"), None);
+        assert_eq!(FileType::Gif.kind(), MediaKind::Animated);
+        assert_eq!(FileType::WebM.to_string(), "video/webm");
+    }
+
+    #[test]
+    fn accepts_matching_content() -> anyhow::Result<()> {
+        let png = b"\x89PNG\r\n\x1a\n\0\0\0\r";
+        let url = "https://i.waifu.pics/a.png?size=large";
+        assert_eq!(verify(url, Some("image/png"), png)?, Some(FileType::Png));
+        assert_eq!(verify(url, None, png)?, Some(FileType::Png));
+        assert_eq!(
+            verify(url, Some("application/octet-stream"), png)?,
+            Some(FileType::Png)
+        );
+        assert_eq!(verify("https://example.com/a.bmp", None, b"BM")?, None);
+        let iso3 = b"\0\0\0\x1cftypiso3\0\0\0\x01";
+        assert_eq!(
+            verify("https://example.com/a.mp4", Some("video/mp4"), iso3)?,
+            Some(FileType::Mp4)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_mismatches() {
+        let gif = b"GIF89a\x01\x00\x01\x00\0\0";
+        match verify("https://i.waifu.pics/a.gif", Some("image/gif"), HTML) {
+            Err(Error::ContentMismatch {
+                url,
+                expected,
+                detected,
+            }) => {
+                assert_eq!(url, "https://i.waifu.pics/a.gif");
+                assert_eq!(expected, ".gif file");
+                assert_eq!(detected, "unrecognized content");
+            }
+            other => panic!("unexpected {other:?}"),
+        }
+        match verify("https://i.waifu.pics/a.gif", Some("image/png"), gif) {
+            Err(Error::ContentMismatch {
+                expected, detected, ..
+            }) => {
+                assert_eq!(expected, "image/png");
+                assert_eq!(detected, "image/gif");
+            }
+            other => panic!("unexpected {other:?}"),
+        }
+        assert!(verify("https://i.waifu.pics/a.png", None, gif).is_err());
+        let heic = b"\0\0\0\x18ftypheic\0\0\0\0";
+        assert!(verify("https://example.com/a.mp4", None, heic).is_err());
+        let avif = b"\0\0\0\x1cftypavif\0\0\0\0";
+        assert!(verify("https://example.com/a.mp4", Some("video/mp4"), avif).is_err());
+        assert!(verify(
+            "https://example.com/image",
+            Some("text/html; charset=utf-8"),
+            HTML
+        )
+        .is_err());
+    }
+}
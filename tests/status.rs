@@ -0,0 +1,103 @@
+mod common;
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, SystemTime};
+
+    use crate::common::{MockServer, Response};
+    use anime_grubber::{
+        agent::Agent,
+        agents::waifu_pics::{Categories, Waifu, SFW},
+        status::{error_for_streaming_status, retry_after, BODY_SNIPPET_LEN},
+        transport::StreamingResponse,
+        Error,
+    };
+    use bytes::Bytes;
+    use futures_util::{stream, StreamExt};
+    use reqwest::{
+        header::{HeaderMap, HeaderValue, RETRY_AFTER},
+        StatusCode,
+    };
+
+    async fn waifu(response: Response) -> (MockServer, Waifu) {
+        let server = MockServer::start(move |_| response.clone()).await;
+        let waifu = Waifu::new(Categories::SFW(SFW::Hug)).with_base_url(server.url());
+        (server, waifu)
+    }
+
+    #[tokio::test]
+    async fn not_found() {
+        let (_server, waifu) = waifu(Response::new(404)).await;
+        assert!(matches!(waifu.get().await, Err(Error::NotFound)));
+        assert!(matches!(waifu.get_many().await, Err(Error::NotFound)));
+    }
+
+    #[tokio::test]
+    async fn rate_limit_with_retry_after() {
+        let (_server, waifu) = waifu(Response::new(429).header("retry-after", "7")).await;
+        for result in [
+            waifu.get().await.map(|_| ()),
+            waifu.get_many().await.map(|_| ()),
+        ] {
+            match result {
+                Err(Error::RateLimit { retry_after }) => {
+                    assert_eq!(retry_after, Some(Duration::from_secs(7)))
+                }
+                other => panic!("unexpected {other:?}"),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn rate_limit_without_retry_after() {
+        let (_server, waifu) = waifu(Response::new(429)).await;
+        assert!(matches!(
+            waifu.get().await,
+            Err(Error::RateLimit { retry_after: None })
+        ));
+    }
+
+    #[tokio::test]
+    async fn request_failed_keeps_url_and_snippet() {
+        let (server, waifu) = waifu(Response::new(503).body("é".repeat(200))).await;
+        match waifu.get().await {
+            Err(Error::RequestFailed { status, url, body }) => {
+                assert_eq!(status.as_u16(), 503);
+                assert_eq!(url, format!("{}/sfw/hug", server.url()));
+                assert_eq!(body.len(), BODY_SNIPPET_LEN);
+                assert!(body.chars().all(|c| c == 'é'));
+            }
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn streaming_error_reads_only_snippet() {
+        let res = StreamingResponse {
+            status: StatusCode::BAD_GATEWAY,
+            url: "https://example.com/a.png".to_owned(),
+            headers: HeaderMap::new(),
+            body: stream::repeat_with(|| Ok(Bytes::from_static(b"oops "))).boxed(),
+        };
+        match error_for_streaming_status(res).await {
+            Err(Error::RequestFailed { body, .. }) => assert_eq!(body.len(), BODY_SNIPPET_LEN),
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+
+    #[test]
+    fn retry_after_http_date() {
+        let mut headers = HeaderMap::new();
+        let date = httpdate::fmt_http_date(SystemTime::now() + Duration::from_secs(60));
+        headers.insert(RETRY_AFTER, HeaderValue::from_str(&date).unwrap());
+        let parsed = retry_after(&headers).unwrap();
+        assert!(parsed <= Duration::from_secs(60) && parsed >= Duration::from_secs(58));
+
+        let past = httpdate::fmt_http_date(SystemTime::UNIX_EPOCH);
+        headers.insert(RETRY_AFTER, HeaderValue::from_str(&past).unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::ZERO));
+
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("soon"));
+        assert_eq!(retry_after(&headers), None);
+    }
+}
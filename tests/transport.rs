@@ -0,0 +1,113 @@
+#[cfg(test)]
+mod test {
+    use anime_grubber::{
+        agent::{Agent, CategorizedAgent},
+        agents::waifu_pics::{Categories, Waifu, NSFW, SFW},
+        context::AgentContext,
+        transport::{MemoryTransport, Response},
+        Error,
+    };
+    use reqwest::{header::CONTENT_TYPE, Method, StatusCode};
+
+    const API: &str = "https://api.waifu.pics";
+
+    fn waifu(transport: &MemoryTransport) -> anime_grubber::Result<Waifu> {
+        Waifu::builder()
+            .categorie(Categories::SFW(SFW::Hug))
+            .transport(transport.clone())
+            .build()
+    }
+
+    #[tokio::test]
+    async fn solo_image() -> anyhow::Result<()> {
+        let transport = MemoryTransport::new().get(
+            format!("{API}/sfw/hug"),
+            Response::json(r#"{"url":"https://i.waifu.pics/hug.gif"}"#),
+        );
+        let image = waifu(&transport)?.get().await?;
+        assert_eq!(image.url, "https://i.waifu.pics/hug.gif");
+        assert_eq!(image.category_path(), "sfw/hug");
+
+        let requests = transport.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].method, Method::GET);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn many_images() -> anyhow::Result<()> {
+        let transport = MemoryTransport::new().post(
+            format!("{API}/many/nsfw/neko"),
+            Response::json(
+                r#"{"files":["https://i.waifu.pics/a.png","https://i.waifu.pics/b.png"]}"#,
+            ),
+        );
+        let images = waifu(&transport)?
+            .get_many_in(Categories::NSFW(NSFW::Neko))
+            .await?;
+        assert_eq!(images.len(), 2);
+        assert_eq!(images[1].url, "https://i.waifu.pics/b.png");
+
+        let request = &transport.requests()[0];
+        assert_eq!(request.headers[CONTENT_TYPE], "application/json");
+        assert_eq!(
+            request.body.as_deref(),
+            Some(br#"{"exclude":[]}"#.as_slice())
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn unknown_route_is_not_found() -> anyhow::Result<()> {
+        let transport = MemoryTransport::new();
+        assert!(matches!(
+            waifu(&transport)?.get().await,
+            Err(Error::NotFound)
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn statuses_are_mapped() -> anyhow::Result<()> {
+        let transport = MemoryTransport::from_fn(|_| {
+            Ok(Response::new(StatusCode::BAD_GATEWAY).body("upstream down"))
+        });
+        match waifu(&transport)?.get().await {
+            Err(Error::RequestFailed { status, url, body }) => {
+                assert_eq!(status, StatusCode::BAD_GATEWAY);
+                assert_eq!(url, format!("{API}/sfw/hug"));
+                assert_eq!(body, "upstream down");
+            }
+            other => panic!("unexpected {other:?}"),
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn transport_errors_are_returned() -> anyhow::Result<()> {
+        let transport = MemoryTransport::from_fn(|_| Err(Error::NotFound));
+        let context = AgentContext::with_transport(transport);
+        let waifu = Waifu::builder().context(&context).build()?;
+        assert!(matches!(waifu.get_many().await, Err(Error::NotFound)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn canned_responses_take_precedence() -> anyhow::Result<()> {
+        let transport = MemoryTransport::from_fn(|request| {
+            Ok(Response::json(format!(
+                r#"{{"url":"{}.gif"}}"#,
+                request.url
+            )))
+        })
+        .get(
+            format!("{API}/sfw/hug"),
+            Response::json(r#"{"url":"https://i.waifu.pics/hug.gif"}"#),
+        );
+        let hug = waifu(&transport)?;
+        assert_eq!(hug.get().await?.url, "https://i.waifu.pics/hug.gif");
+        let pat = hug.for_categorie(Categories::SFW(SFW::Pat));
+        assert_eq!(pat.get().await?.url, format!("{API}/sfw/pat.gif"));
+        Ok(())
+    }
+}
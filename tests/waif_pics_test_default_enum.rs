@@ -1,5 +1,6 @@
 #[cfg(test)]
 mod test_default {
+    use anime_grubber::agents::waifu_pics::{Categories, NSFW, SFW};
     use anime_grubber::gen_enum;
 
     #[test]
@@ -77,4 +78,24 @@ mod test_default {
         let default2 = TestEnum::default();
         assert_eq!(<&str>::from(&default1), <&str>::from(&default2));
     }
+
+    #[test]
+    fn test_variants() {
+        gen_enum!(Inner, [One, Two]);
+        gen_enum!(Outer, [Value(Inner), Other(Inner)]);
+
+        assert_eq!(
+            Outer::variants(),
+            [
+                Outer::Value(Inner::One),
+                Outer::Value(Inner::Two),
+                Outer::Other(Inner::One),
+                Outer::Other(Inner::Two),
+            ]
+        );
+        assert_eq!(
+            Categories::variants().len(),
+            SFW::variants().len() + NSFW::variants().len()
+        );
+    }
 }
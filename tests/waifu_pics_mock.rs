@@ -0,0 +1,310 @@
+mod common;
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use crate::common::{MockServer, Response};
+    use anime_grubber::{
+        agent::{Agent, CategorizedAgent},
+        agents::waifu_pics::{Categories, Waifu, NSFW, SFW},
+        context::AgentContext,
+        Error,
+    };
+    use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+
+    fn many(files: &[&str]) -> Response {
+        let files: Vec<String> = files.iter().map(|file| format!("\"{file}\"")).collect();
+        Response::json(format!("{{\"files\":[{}]}}", files.join(",")))
+    }
+
+    #[tokio::test]
+    async fn many_sends_empty_exclude() -> anyhow::Result<()> {
+        let server = MockServer::start(|_| many(&["https://i.waifu.pics/a.gif"])).await;
+        let waifu = Waifu::new(Categories::SFW(SFW::Hug)).with_base_url(server.url());
+
+        let images = waifu.get_many().await?;
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].category_path(), Categories::SFW(SFW::Hug).path());
+
+        let requests = server.requests();
+        assert_eq!(requests[0].method, "POST");
+        assert_eq!(requests[0].target, "/many/sfw/hug");
+        assert_eq!(requests[0].body_str(), r#"{"exclude":[]}"#);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn many_excluding_sends_exclude() -> anyhow::Result<()> {
+        let server = MockServer::start(|request| {
+            if request.body_str().contains("a.gif") {
+                many(&["https://i.waifu.pics/c.gif"])
+            } else {
+                many(&["https://i.waifu.pics/a.gif", "https://i.waifu.pics/b.gif"])
+            }
+        })
+        .await;
+        let waifu = Waifu::new(Categories::SFW(SFW::Hug)).with_base_url(server.url());
+
+        let shown = waifu.get_many().await?;
+        let fresh = waifu.get_many_excluding(shown.iter()).await?;
+        assert_eq!(fresh.len(), 1);
+        assert_eq!(fresh[0].url, "https://i.waifu.pics/c.gif");
+
+        let requests = server.requests();
+        assert_eq!(
+            requests[1].body_str(),
+            r#"{"exclude":["https://i.waifu.pics/a.gif","https://i.waifu.pics/b.gif"]}"#
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn many_excluding_file_names() -> anyhow::Result<()> {
+        let server = MockServer::start(|_| many(&[])).await;
+        let waifu = Waifu::new(Categories::SFW(SFW::Hug)).with_base_url(server.url());
+
+        waifu.get_many_excluding(["a.gif", "b.gif"]).await?;
+        assert_eq!(
+            server.requests()[0].body_str(),
+            r#"{"exclude":["a.gif","b.gif"]}"#
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn solo_uses_base_url() -> anyhow::Result<()> {
+        let server =
+            MockServer::start(|_| Response::json(r#"{"url":"https://i.waifu.pics/a.png"}"#)).await;
+        let waifu = Waifu::new(Categories::SFW(SFW::Pat)).with_base_url(server.url());
+
+        let image = waifu.get().await?;
+        assert_eq!(image.url, "https://i.waifu.pics/a.png");
+        assert_eq!(server.requests()[0].target, "/sfw/pat");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn batch_trims_to_count() -> anyhow::Result<()> {
+        let files: Vec<String> = (0..30)
+            .map(|i| format!("https://i.waifu.pics/{i}.gif"))
+            .collect();
+        let server = MockServer::start(move |_| {
+            let files: Vec<&str> = files.iter().map(String::as_str).collect();
+            many(&files)
+        })
+        .await;
+        let waifu = Waifu::new(Categories::SFW(SFW::Hug)).with_base_url(server.url());
+
+        let images = waifu.get_batch(5).await?;
+        assert_eq!(images.len(), 5);
+        assert_eq!(server.requests().len(), 1);
+        assert_eq!(waifu.max_batch(), 30);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn batch_repeats_with_exclude() -> anyhow::Result<()> {
+        let round = AtomicUsize::new(0);
+        let server = MockServer::start(move |_| {
+            let i = round.fetch_add(1, Ordering::SeqCst) * 2;
+            many(&[
+                &format!("https://i.waifu.pics/{i}.gif"),
+                &format!("https://i.waifu.pics/{}.gif", i + 1),
+                "https://i.waifu.pics/0.gif",
+            ])
+        })
+        .await;
+        let waifu = Waifu::new(Categories::SFW(SFW::Hug)).with_base_url(server.url());
+
+        let images = waifu.get_batch(5).await?;
+        let urls: Vec<&str> = images.iter().map(|image| image.url.as_str()).collect();
+        assert_eq!(
+            urls,
+            [
+                "https://i.waifu.pics/0.gif",
+                "https://i.waifu.pics/1.gif",
+                "https://i.waifu.pics/2.gif",
+                "https://i.waifu.pics/3.gif",
+                "https://i.waifu.pics/4.gif",
+            ]
+        );
+        let requests = server.requests();
+        assert_eq!(requests.len(), 3);
+        assert!(requests[2]
+            .body_str()
+            .contains("https://i.waifu.pics/3.gif"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn batch_stops_when_source_runs_dry() -> anyhow::Result<()> {
+        let server = MockServer::start(|_| many(&["https://i.waifu.pics/a.gif"])).await;
+        let waifu = Waifu::new(Categories::SFW(SFW::Hug)).with_base_url(server.url());
+
+        let images = waifu.get_batch(10).await?;
+        assert_eq!(images.len(), 1);
+        assert_eq!(server.requests().len(), 4);
+        Ok(())
+    }
+
+    fn solo_echo() -> impl Fn(&crate::common::Request) -> Response {
+        |request| {
+            Response::json(format!(
+                r#"{{"url":"https://i.waifu.pics{}.png"}}"#,
+                request.target
+            ))
+        }
+    }
+
+    #[tokio::test]
+    async fn random_keeps_kind() -> anyhow::Result<()> {
+        let server = MockServer::start(solo_echo()).await;
+        let waifu = Waifu::new(Categories::NSFW(NSFW::Neko)).with_base_url(server.url());
+
+        for _ in 0..10 {
+            let image = waifu.get_random().await?;
+            assert_eq!(image.category, "nsfw");
+        }
+        for request in server.requests() {
+            assert!(request.target.starts_with("/nsfw/"));
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn random_reports_category_from_pool() -> anyhow::Result<()> {
+        let server = MockServer::start(solo_echo()).await;
+        let pool = [SFW::Hug, SFW::Pat].map(Categories::SFW);
+        let waifu = Waifu::new(Categories::SFW(SFW::Dance))
+            .with_base_url(server.url())
+            .with_random_pool(pool);
+
+        for _ in 0..10 {
+            let image = waifu.get_random().await?;
+            let category: Categories = image.category_path().parse()?;
+            assert!(pool.contains(&category));
+            let expected = format!("https://i.waifu.pics/{}.png", category.path());
+            assert_eq!(image.url, expected);
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn builder_sets_headers() -> anyhow::Result<()> {
+        let server = MockServer::start(solo_echo()).await;
+        let waifu = Waifu::builder()
+            .categorie(Categories::SFW(SFW::Wave))
+            .base_url(format!("{}/", server.url()))
+            .user_agent("grubber-test/1.0")
+            .header(
+                HeaderName::from_static("x-correlation-id"),
+                HeaderValue::from_static("42"),
+            )
+            .build()?;
+
+        waifu.get().await?;
+        let request = &server.requests()[0];
+        assert_eq!(request.target, "/sfw/wave");
+        assert_eq!(request.header("user-agent"), Some("grubber-test/1.0"));
+        assert_eq!(request.header("x-correlation-id"), Some("42"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn builder_uses_injected_client() -> anyhow::Result<()> {
+        let server = MockServer::start(|_| many(&[])).await;
+        let mut headers = HeaderMap::new();
+        headers.insert("x-client", HeaderValue::from_static("injected"));
+        let client = reqwest::Client::builder()
+            .default_headers(headers)
+            .build()?;
+        let waifu = Waifu::builder()
+            .base_url(server.url())
+            .client(client)
+            .build()?;
+
+        waifu.get_many().await?;
+        let request = &server.requests()[0];
+        assert_eq!(request.header("x-client"), Some("injected"));
+        assert_eq!(request.header("content-type"), Some("application/json"));
+        Ok(())
+    }
+
+    #[test]
+    fn builder_reports_client_error() {
+        let result = Waifu::builder().user_agent("bad\nagent").build();
+        assert!(matches!(result, Err(Error::Client(_))));
+    }
+
+    #[tokio::test]
+    async fn for_categorie_keeps_settings() -> anyhow::Result<()> {
+        let server = MockServer::start(solo_echo()).await;
+        let hug = Waifu::builder()
+            .categorie(Categories::SFW(SFW::Hug))
+            .base_url(server.url())
+            .user_agent("grubber-test/1.0")
+            .build()?;
+        let neko = hug.for_categorie(Categories::NSFW(NSFW::Neko));
+        assert_eq!(hug.categorie, Categories::SFW(SFW::Hug));
+
+        let image = neko.get().await?;
+        assert_eq!(image.category_path(), Categories::NSFW(NSFW::Neko).path());
+        let request = &server.requests()[0];
+        assert_eq!(request.target, "/nsfw/neko");
+        assert_eq!(request.header("user-agent"), Some("grubber-test/1.0"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn agents_share_context() -> anyhow::Result<()> {
+        let server = MockServer::start(solo_echo()).await;
+        let context = AgentContext::shared()?;
+        for categorie in [SFW::Hug, SFW::Pat].map(Categories::SFW) {
+            let waifu = Waifu::builder()
+                .categorie(categorie)
+                .base_url(server.url())
+                .context(&context)
+                .build()?;
+            assert_eq!(waifu.get().await?.category_path(), categorie.path());
+        }
+        assert_eq!(server.requests().len(), 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn categories_per_call() -> anyhow::Result<()> {
+        let server = MockServer::start(|request| {
+            if request.method == "POST" {
+                many(&["https://i.waifu.pics/many.gif"])
+            } else {
+                solo_echo()(request)
+            }
+        })
+        .await;
+        let mut waifu = Waifu::new(Categories::SFW(SFW::Hug)).with_base_url(server.url());
+
+        let (pat, neko, many) = tokio::join!(
+            waifu.get_in(Categories::SFW(SFW::Pat)),
+            waifu.get_in(Categories::NSFW(NSFW::Neko)),
+            waifu.get_many_in(Categories::SFW(SFW::Kiss)),
+        );
+        assert_eq!(pat?.category_path(), Categories::SFW(SFW::Pat).path());
+        assert_eq!(neko?.category_path(), Categories::NSFW(NSFW::Neko).path());
+        assert_eq!(many?[0].category_path(), Categories::SFW(SFW::Kiss).path());
+        assert_eq!(waifu.category(), Categories::SFW(SFW::Hug));
+
+        let mut targets: Vec<String> = server
+            .requests()
+            .into_iter()
+            .map(|request| request.target)
+            .collect();
+        targets.sort();
+        assert_eq!(targets, ["/many/sfw/kiss", "/nsfw/neko", "/sfw/pat"]);
+
+        waifu.set_category(Categories::NSFW(NSFW::Trap));
+        assert_eq!(waifu.categorie, Categories::NSFW(NSFW::Trap));
+        assert_eq!(Waifu::categories().len(), Categories::variants().len());
+        Ok(())
+    }
+}